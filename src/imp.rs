@@ -9,6 +9,7 @@ use proc_macro2::TokenTree;
 use quote::quote;
 use quote::quote_spanned;
 use quote::ToTokens;
+use std::collections::HashSet;
 use std::iter::once;
 use std::mem;
 use std::ops::Deref;
@@ -16,9 +17,11 @@ use venial::parse_declaration;
 use venial::Attribute;
 use venial::AttributeValue;
 use venial::Declaration;
+use venial::GenericBound;
 use venial::GenericParam;
 use venial::GenericParamList;
 use venial::StructFields;
+use venial::WhereClause;
 
 fn stream_span(input: impl Iterator<Item = impl Deref<Target = TokenTree>>) -> Option<Span> {
     let mut ret = None;
@@ -35,50 +38,156 @@ fn stream_span(input: impl Iterator<Item = impl Deref<Target = TokenTree>>) -> O
     ret
 }
 
+/// How tuple fields are numbered in name hints, set by `#[structstruck::numbering(…)]`
+#[derive(Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Numbering {
+    /// Number all fields but the first
+    #[default]
+    SkipFirst,
+    Always,
+    /// Only number a field if the bare name was already taken by a previous field
+    OnCollision,
+    Never,
+}
+impl Numbering {
+    fn parse(attr: &Attribute) -> Option<Self> {
+        match attr.get_value_tokens() {
+            [TokenTree::Ident(mode)] => match mode.to_string().as_str() {
+                "skip_first" => Some(Numbering::SkipFirst),
+                "always" => Some(Numbering::Always),
+                "on_collision" => Some(Numbering::OnCollision),
+                "never" => Some(Numbering::Never),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
 #[derive(Default, Clone, Copy)]
 pub(crate) struct NameHints<'a> {
     long: bool,
+    from_doc: bool,
+    numbering: Numbering,
     parent_name: &'a str,
     variant_name: Option<&'a str>,
     field_name: Option<&'a str>,
+    /// From `#[structstruck::raw_names]`, names aren't turned into PascalCase
+    raw: bool,
+    /// Goes between the parts of long names, from `#[structstruck::config(separator = …)]`
+    separator: &'a str,
+    /// From `#[structstruck::wrapper_names]`
+    wrapper_names: bool,
+    /// The type whose arguments are being looked at, e.g. Box in `Box<struct {…}>`
+    wrapper: Option<&'a Ident>,
+    /// From `#[structstruck::map_names]`, see [`recurse_through_type_list`]
+    map_names: bool,
+    /// Generic parameters and where clause of the enclosing declarations, see [`inherit_generics`]
+    generics: Option<&'a GenericParamList>,
+    where_clause: Option<&'a WhereClause>,
+    /// From `#[structstruck::name_prefix(…)]` and `#[structstruck::name_suffix(…)]`
+    prefix: &'a str,
+    suffix: &'a str,
+    number_separator: &'a str,
+    name_map: &'a [NameRule],
 }
 impl<'a> NameHints<'a> {
-    fn from(parent_name: &'a str, attributes: &mut Vec<Attribute>) -> Self {
+    fn from(parent_name: &'a str, attributes: &mut Vec<Attribute>, ret: &mut TokenStream) -> Self {
         let mut long = false;
+        let mut from_doc = false;
+        let mut wrapper_names = false;
+        let mut map_names = false;
+        let mut raw = false;
+        let mut numbering = Numbering::default();
+        // Attributes from structstruck::each come first, so the most specific setting wins
         attributes.retain(|attr| {
-            let enable_long = check_crate_attr(attr, "long_names");
-            long |= enable_long;
-            !enable_long
+            if check_crate_attr(attr, "long_names") {
+                long = true;
+            } else if check_crate_attr(attr, "short_names") {
+                long = false;
+            } else if check_crate_attr(attr, "name_from_doc") {
+                from_doc = true;
+            } else if check_crate_attr(attr, "wrapper_names") {
+                wrapper_names = true;
+            } else if check_crate_attr(attr, "map_names") {
+                map_names = true;
+            } else if check_crate_attr(attr, "raw_names") {
+                raw = true;
+            } else if check_crate_attr(attr, "numbering") {
+                match Numbering::parse(attr) {
+                    Some(n) => numbering = n,
+                    None => {
+                        report_attr_error(attr, ret, "#[structstruck::numbering(…)]: unknown mode")
+                    }
+                }
+            } else {
+                return true;
+            }
+            false
         });
         NameHints {
             long,
+            from_doc,
+            numbering,
             parent_name,
             variant_name: None,
             field_name: None,
+            raw,
+            separator: "",
+            wrapper_names,
+            wrapper: None,
+            map_names,
+            generics: None,
+            where_clause: None,
+            prefix: "",
+            suffix: "",
+            number_separator: "",
+            name_map: &[],
         }
     }
 
     fn get_name_hint(&self, num: Option<usize>, span: Span) -> Ident {
-        let num = num.filter(|&n| n > 0).map(|n| n.to_string());
-        let names = match self.long {
-            true => &[
-                Some(self.parent_name),
-                self.variant_name,
-                self.field_name,
-                num.as_deref(),
-            ][..],
-            false => &[
-                self.field_name
-                    .or(self.variant_name)
-                    .or(Some(self.parent_name)),
-                num.as_deref(),
-            ][..],
-        };
-        let name = names
-            .into_iter()
-            .map(|x| x.map(pascal_case).unwrap_or(String::new()))
-            .fold(String::new(), |s, p| s + &p);
-        Ident::new(&name, span)
+        // Any of the names may be raw identifiers, e.g. r#type
+        let unraw = |name: &'a str| name.strip_prefix("r#").unwrap_or(name);
+        let case = |name: &str| match self.raw {
+            true => name.to_owned(),
+            false => pascal_case(name),
+        };
+        let field = self.field_name.map(unraw).map(|field| {
+            self.name_map
+                .iter()
+                .find_map(|rule| rule.apply(field))
+                .unwrap_or_else(|| case(field))
+        });
+        let variant = self.variant_name.map(unraw).map(case);
+        // A parent that was named with a separator already would lose it to pascal_case
+        let parent = match self.separator {
+            "" => case(unraw(self.parent_name)),
+            _ => unraw(self.parent_name).to_owned(),
+        };
+        let name = match self.long {
+            true => {
+                let separator = match (self.separator, self.raw) {
+                    // Without PascalCase, the parts wouldn't be distinguishable
+                    ("", true) => "_",
+                    (separator, _) => separator,
+                };
+                [Some(parent), variant, field]
+                    .into_iter()
+                    .flatten()
+                    .collect::<Vec<_>>()
+                    .join(separator)
+            }
+            false => field.or(variant).unwrap_or(parent),
+        };
+        // The separator isn't run through pascal_case, it would eat underscores
+        let num = num.map_or(String::new(), |n| format!("{}{}", self.number_separator, n));
+        let name = valid_type_name(format!("{}{}{}{}", self.prefix, name, num, self.suffix));
+        match is_keyword(&name) {
+            // Only possible with raw_names, e.g. for a field r#type
+            true => Ident::new_raw(&name, span),
+            false => Ident::new(&name, span),
+        }
     }
 
     fn with_field_name(&self, field_name: &'a str) -> Self {
@@ -88,12 +197,70 @@ impl<'a> NameHints<'a> {
         }
     }
 
+    fn with_wrapper(&self, wrapper: Option<&'a Ident>) -> Self {
+        Self { wrapper, ..*self }
+    }
+
+    /// Prefixes a name hint with the type it is wrapped in, if asked to, e.g. `BoxEngine`
+    fn wrapped_name(&self, name: &Ident) -> Ident {
+        match (self.wrapper_names, self.wrapper) {
+            (true, Some(wrapper)) => {
+                let wrapper = wrapper.to_string();
+                let wrapper = wrapper.strip_prefix("r#").unwrap_or(&wrapper);
+                let unwrapped = name.to_string();
+                let unwrapped = unwrapped.strip_prefix("r#").unwrap_or(&unwrapped);
+                Ident::new(&format!("{}{}", wrapper, unwrapped), name.span())
+            }
+            _ => name.clone(),
+        }
+    }
+
     fn with_variant_name(&self, variant_name: &'a str) -> Self {
         Self {
             variant_name: Some(variant_name),
             ..*self
         }
     }
+
+    fn with_generics(
+        &self,
+        generics: Option<&'a GenericParamList>,
+        where_clause: Option<&'a WhereClause>,
+    ) -> Self {
+        Self {
+            generics,
+            where_clause,
+            ..*self
+        }
+    }
+
+    /// The declaration's own generics, or the inherited ones if it has none
+    fn with_own_generics(
+        &self,
+        generics: &'a Option<GenericParamList>,
+        where_clause: &'a Option<WhereClause>,
+        (inherited_generics, inherited_where): (
+            Option<&'a GenericParamList>,
+            Option<&'a WhereClause>,
+        ),
+    ) -> Self {
+        match generics {
+            Some(generics) => self.with_generics(Some(generics), where_clause.as_ref()),
+            None => self.with_generics(inherited_generics, inherited_where),
+        }
+    }
+
+    fn with_affixes(&self, affixes: &'a NameAffixes) -> Self {
+        Self {
+            prefix: &affixes.prefix,
+            suffix: &affixes.suffix,
+            number_separator: &affixes.number_separator,
+            name_map: &affixes.name_map,
+            separator: &affixes.separator,
+            raw: affixes.raw.unwrap_or(self.raw),
+            ..*self
+        }
+    }
 }
 
 fn check_crate_attr(attr: &Attribute, attr_name: &str) -> bool {
@@ -109,6 +276,224 @@ fn check_crate_attr(attr: &Attribute, attr_name: &str) -> bool {
     )
 }
 
+/// Removes all occurrences of `#[structstruck::<attr_name>]`, returns whether there were any
+fn take_crate_attr(attributes: &mut Vec<Attribute>, attr_name: &str) -> bool {
+    let len = attributes.len();
+    attributes.retain(|attr| !check_crate_attr(attr, attr_name));
+    attributes.len() != len
+}
+
+/// Strings added to generated names
+#[derive(Default)]
+struct NameAffixes {
+    prefix: String,
+    suffix: String,
+    /// Goes between a name and the number of its tuple field, e.g. `_` for `Tuple_2`
+    number_separator: String,
+    /// From `#[structstruck::name_map(…)]`, the first matching rule is used
+    name_map: Vec<NameRule>,
+    /// From `#[structstruck::config(…)]`, see [`NameHints`]
+    separator: String,
+    raw: Option<bool>,
+}
+
+/// `suffix("_cfg") => suffix("Config")` names a field `db_cfg` `DbConfig`
+struct NameRule {
+    /// `prefix`, `suffix`, or `name`, which matches the whole field name
+    pattern: (String, String),
+    /// `prefix`, `suffix`, or `replace`, which replaces the whole name
+    rewrite: (String, String),
+}
+impl NameRule {
+    fn parse(tokens: &[TokenTree]) -> Option<Self> {
+        let part = |kind: &TokenTree, value: &TokenTree, kinds: &[&str]| match (kind, value) {
+            (TokenTree::Ident(kind), TokenTree::Group(value))
+                if kinds.iter().any(|k| kind == k)
+                    && value.delimiter() == Delimiter::Parenthesis =>
+            {
+                match &value.stream().into_iter().collect::<Vec<_>>()[..] {
+                    [TokenTree::Literal(lit)] => {
+                        unquote_str_literal(&lit.to_string()).map(|value| (kind.to_string(), value))
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        match tokens {
+            [kind, value, TokenTree::Punct(eq), TokenTree::Punct(gt), new_kind, new_value]
+                if eq.as_char() == '=' && eq.spacing() == Spacing::Joint && gt.as_char() == '>' =>
+            {
+                Some(NameRule {
+                    pattern: part(kind, value, &["prefix", "suffix", "name"])?,
+                    rewrite: part(new_kind, new_value, &["prefix", "suffix", "replace"])?,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn apply(&self, field_name: &str) -> Option<String> {
+        let rest = match (self.pattern.0.as_str(), self.pattern.1.as_str()) {
+            ("prefix", p) => field_name.strip_prefix(p)?,
+            ("suffix", s) => field_name.strip_suffix(s)?,
+            (_, name) => (field_name == name).then_some("")?,
+        };
+        let rest = pascal_case(rest);
+        Some(match (self.rewrite.0.as_str(), self.rewrite.1.as_str()) {
+            ("prefix", p) => format!("{}{}", p, rest),
+            ("suffix", s) => format!("{}{}", rest, s),
+            (_, name) => name.to_string(),
+        })
+    }
+}
+
+/// Takes `#[structstruck::name_prefix("…")]`, `#[structstruck::name_suffix("…")]`,
+/// and `#[structstruck::number_separator("…")]`, the last one of each wins.
+/// Also takes the rules of `#[structstruck::name_map(…)]`, later attributes' rules go first.
+fn take_name_affixes(attributes: &mut Vec<Attribute>, ret: &mut TokenStream) -> NameAffixes {
+    let mut affixes = NameAffixes::default();
+    attributes.retain(|attr| {
+        if check_crate_attr(attr, "config") {
+            name_config(attr, &mut affixes, ret);
+            return false;
+        }
+        if check_crate_attr(attr, "name_map") {
+            let rules = attr
+                .get_value_tokens()
+                .split(|t| matches!(t, TokenTree::Punct(p) if p.as_char() == ','))
+                .filter(|rule| !rule.is_empty())
+                .map(NameRule::parse)
+                .collect::<Option<Vec<_>>>();
+            match rules {
+                Some(rules) if matches!(attr.value, AttributeValue::Group(..)) => {
+                    affixes.name_map.splice(0..0, rules);
+                }
+                _ => report_attr_error(attr, ret,
+                    "#[structstruck::name_map(…)]: expected e.g. name_map(suffix(\"_cfg\") => suffix(\"Config\"))",
+                ),
+            }
+            return false;
+        }
+        let (affix, attr_name) = if check_crate_attr(attr, "name_prefix") {
+            (&mut affixes.prefix, "name_prefix")
+        } else if check_crate_attr(attr, "name_suffix") {
+            (&mut affixes.suffix, "name_suffix")
+        } else if check_crate_attr(attr, "number_separator") {
+            (&mut affixes.number_separator, "number_separator")
+        } else {
+            return true;
+        };
+        let value = match attr.get_value_tokens() {
+            [TokenTree::Literal(lit)] => unquote_str_literal(&lit.to_string()),
+            _ => None,
+        };
+        match value {
+            Some(value) => *affix = value,
+            None => report_attr_error(attr, ret,
+                &format!("#[structstruck::{}(…)]: expected a string", attr_name),
+            ),
+        }
+        false
+    });
+    affixes
+}
+
+/// `#[structstruck::config(separator = "_", case = "raw", …)]` sets several naming options at once
+fn name_config(attr: &Attribute, affixes: &mut NameAffixes, ret: &mut TokenStream) {
+    let settings = attr
+        .get_value_tokens()
+        .split(|t| matches!(t, TokenTree::Punct(p) if p.as_char() == ','))
+        .filter(|setting| !setting.is_empty());
+    for setting in settings {
+        let (key, value) = match setting {
+            [TokenTree::Ident(key), TokenTree::Punct(eq), TokenTree::Literal(lit)]
+                if eq.as_char() == '=' =>
+            {
+                match unquote_str_literal(&lit.to_string()) {
+                    Some(value) => (key, value),
+                    None => {
+                        report_error(
+                            Some(lit.span()),
+                            ret,
+                            "#[structstruck::config(…)]: expected a string",
+                        );
+                        continue;
+                    }
+                }
+            }
+            _ => {
+                report_error(
+                    stream_span(setting.iter()),
+                    ret,
+                    "#[structstruck::config(…)]: expected e.g. config(separator = \"_\", case = \"raw\")",
+                );
+                continue;
+            }
+        };
+        match key.to_string().as_str() {
+            "prefix" => affixes.prefix = value,
+            "suffix" => affixes.suffix = value,
+            "number_separator" => affixes.number_separator = value,
+            "separator" => affixes.separator = value,
+            "case" if value == "pascal" => affixes.raw = Some(false),
+            "case" if value == "raw" => affixes.raw = Some(true),
+            "case" => report_error(
+                Some(key.span()),
+                ret,
+                &format!(
+                    "#[structstruck::config(…)]: unknown case {:?}, expected \"pascal\" or \"raw\"",
+                    value
+                ),
+            ),
+            _ => report_error(
+                Some(key.span()),
+                ret,
+                &format!(
+                    "#[structstruck::config(…)]: unknown key {}, expected separator, case, prefix, suffix, or number_separator",
+                    key
+                ),
+            ),
+        }
+    }
+}
+
+/// Extracts a name hint from the first line of a doc comment,
+/// but only if that line is a plain phrase like `Storage settings`.
+/// Returns the words joined by underscores, ready for [`pascal_case`].
+fn doc_name_hint(attributes: &[Attribute]) -> Option<String> {
+    let doc = attributes
+        .iter()
+        .find_map(|attr| match (&attr.path[..], &attr.value) {
+            ([TokenTree::Ident(doc)], AttributeValue::Equals(_, value)) if doc == "doc" => {
+                match &value[..] {
+                    [TokenTree::Literal(lit)] => unquote_str_literal(&lit.to_string()),
+                    _ => None,
+                }
+            }
+            _ => None,
+        })?;
+    let line = doc.lines().map(str::trim).find(|l| !l.is_empty())?;
+    let clean = line
+        .split_whitespace()
+        .all(|w| w.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'));
+    match clean {
+        true => Some(line.split_whitespace().collect::<Vec<_>>().join("_")),
+        false => None,
+    }
+}
+
+/// Strips the quotes off a (raw) string literal. Gives up on escape sequences.
+fn unquote_str_literal(lit: &str) -> Option<String> {
+    let lit = match lit.strip_prefix('r') {
+        Some(raw) => raw.trim_matches('#'),
+        None if lit.contains('\\') => return None,
+        None => lit,
+    };
+    let lit = lit.strip_prefix('"')?.strip_suffix('"')?;
+    Some(lit.to_owned())
+}
+
 /// capitalizes the first letter of each word and the one after an underscore
 /// e.g. `foo_bar` -> `FooBar`
 /// this also keeps consecutive uppercase letters
@@ -128,16 +513,105 @@ fn pascal_case(s: &str) -> String {
     ret
 }
 
+/// Names like `0` (from a field `_0`), `2fa`, or nothing at all (from `_`) aren't identifiers,
+/// they get a `Field` prefix
+pub(crate) fn valid_type_name(name: String) -> String {
+    match name.chars().next() {
+        Some(c) if !c.is_ascii_digit() => name,
+        _ => format!("Field{}", name),
+    }
+}
+
+/// Keywords that can only be used as identifiers in their raw form, `r#type`
+fn is_keyword(name: &str) -> bool {
+    [
+        "as", "async", "await", "break", "const", "continue", "dyn", "else", "enum", "extern",
+        "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut",
+        "pub", "ref", "return", "static", "struct", "trait", "true", "type", "unsafe", "use",
+        "where", "while", "abstract", "become", "box", "do", "final", "gen", "macro", "override",
+        "priv", "try", "typeof", "unsized", "virtual", "yield",
+    ]
+    .contains(&name)
+}
+
+/// inverse of [`pascal_case`]: `FooBar` -> `foo_bar`, and `HTTPServer` -> `http_server`
+fn snake_case(s: &str) -> String {
+    let chars = s.chars().collect::<Vec<_>>();
+    let mut ret = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if c.is_ascii_uppercase() {
+            let prev = i.checked_sub(1).map(|i| chars[i]);
+            let next = chars.get(i + 1);
+            let word_start = match prev {
+                Some(prev) if prev.is_ascii_uppercase() => {
+                    next.is_some_and(|next| next.is_ascii_lowercase())
+                }
+                Some(prev) => prev != '_',
+                None => false,
+            };
+            if word_start {
+                ret.push('_');
+            }
+            ret.push(c.to_ascii_lowercase());
+        } else {
+            ret.push(c);
+        }
+    }
+    ret
+}
+
 pub(crate) fn recurse_through_definition(
     input: TokenStream,
-    mut strike_attrs: Vec<Attribute>,
+    strike_attrs: Vec<StrikeAttr>,
+    make_pub: bool,
+    ret: &mut TokenStream,
+) -> Option<GenericParamList> {
+    strike_definition(input, strike_attrs, make_pub, false, None, ret)
+}
+
+/// Only emits the declarations nested in the given one, not the given declaration itself
+pub(crate) fn recurse_through_children(input: TokenStream, ret: &mut TokenStream) {
+    strike_definition(input, vec![], false, true, None, ret);
+}
+
+fn strike_definition(
+    input: TokenStream,
+    mut strike_attrs: Vec<StrikeAttr>,
     make_pub: bool,
+    children_only: bool,
+    // None for the declaration given to the macro, Some for nested ones
+    inherited: Option<(Option<&GenericParamList>, Option<&WhereClause>)>,
     ret: &mut TokenStream,
 ) -> Option<GenericParamList> {
+    let nested = inherited.is_some();
+    let (inherited_generics, inherited_where) = inherited.unwrap_or_default();
     let input_vec = input.into_iter().collect::<Vec<TokenTree>>();
     let span = stream_span(input_vec.iter());
+    if recurse_through_const_item(&input_vec, &strike_attrs, ret)
+        || recurse_through_impl(&input_vec, &strike_attrs, ret)
+    {
+        return None;
+    }
+    let kind = item_kind(&input_vec);
+    if !["struct", "enum", "union", "type", "fn"].contains(&kind.as_str()) {
+        // Not all of these make it through venial
+        report_error(
+            span,
+            ret,
+            &format!(
+                "Unsupported declaration: {} (only struct, enum, union, type, fn, and impl are allowed)",
+                kind
+            ),
+        );
+        return None;
+    }
+    let input_vec = recurse_through_generic_params(input_vec, &strike_attrs, ret);
+    let input_vec = recurse_through_where_clause(input_vec, &strike_attrs, ret);
+    let (input_vec, defaults) = take_generic_defaults(input_vec, ret);
+    let input_vec = group_fn_return(input_vec);
     let input = hack_append_type_decl_semicolon(input_vec);
     let input = move_out_inner_attrs(input);
+    let input = move_each_filters(input);
     let mut parsed = match parse_declaration(input) {
         Ok(parsed) => parsed,
         Err(e) => {
@@ -146,11 +620,69 @@ pub(crate) fn recurse_through_definition(
             return None;
         }
     };
+    restore_generic_defaults(&mut parsed, &defaults);
+    if let Declaration::Function(f) = &mut parsed {
+        ungroup_fn_return(f);
+    }
+    // For #[structstruck::generate_only], the declaration is emitted as written, minus the settings
+    let generate_only = take_setting(&mut parsed, |attrs| take_crate_attr(attrs, "generate_only"));
+    let original = generate_only.then(|| {
+        let mut original = parsed.clone();
+        if let Some(attributes) = attributes_mut(&mut original) {
+            attributes.retain(|attr| !is_crate_attr(attr));
+        }
+        original
+    });
+    let kind_and_name = match &parsed {
+        Declaration::Struct(s) => Some(("struct", s.name.clone())),
+        Declaration::Enum(e) => Some(("enum", e.name.clone())),
+        Declaration::Union(u) => Some(("union", u.name.clone())),
+        Declaration::TyDefinition(t) => Some(("type", t.name.clone())),
+        Declaration::Function(f) => Some(("fn", f.name.clone())),
+        _ => None,
+    };
+    // Before the settings are taken, so they can come from structstruck::each, too
+    if let (Some((kind, name)), Some(attributes)) = (&kind_and_name, attributes_mut(&mut parsed)) {
+        strike_through_attributes(attributes, &mut strike_attrs, kind, name, ret);
+    }
+    let debug = take_setting(&mut parsed, |attrs| take_crate_attr(attrs, "debug"));
+    let vis = take_setting(&mut parsed, |attrs| take_vis_attr(attrs, ret));
+    // #[structstruck::private] is short for #[structstruck::vis()]
+    let vis = match take_setting(&mut parsed, |attrs| take_crate_attr(attrs, "private")) {
+        true => Some(None),
+        false => vis,
+    };
+    let reexport = take_setting(&mut parsed, |attrs| take_reexport_attr(attrs, ret));
+    let emit_after = take_setting(&mut parsed, |attrs| take_emit_after_attr(attrs, ret));
+    let max_types = take_setting(&mut parsed, |attrs| take_max_types_attr(attrs, ret));
+    let alias = take_setting(&mut parsed, |attrs| take_alias_attr(attrs, ret));
+    // Also needed to check the nested declarations' names against this one's
+    let ret_start = ret.clone().into_iter().count();
+    // Items that go after the declaration, e.g. impls
+    let mut after = TokenStream::new();
+    // Whether to generate an accessor, and whether the field's type is a nested declaration
+    let mut accessor = None;
     match &mut parsed {
         Declaration::Struct(s) => {
-            strike_through_attributes(&mut s.attributes, &mut strike_attrs, ret);
             let name = s.name.to_string();
-            let path = &NameHints::from(&name, &mut s.attributes);
+            let affixes = take_name_affixes(&mut s.attributes, ret);
+            let path = &NameHints::from(&name, &mut s.attributes, ret)
+                .with_affixes(&affixes)
+                .with_own_generics(
+                    &s.generic_params,
+                    &s.where_clause,
+                    (inherited_generics, inherited_where),
+                );
+            let defaults = take_field_defaults(&mut s.fields, ret);
+            let debug_skips = take_debug_skips(&mut s.fields);
+            if take_crate_attr(&mut s.attributes, "accessors") {
+                accessor = match &s.fields {
+                    StructFields::Tuple(t) if t.fields.len() == 1 => {
+                        Some(wraps_declaration(&s.fields))
+                    }
+                    _ => None,
+                };
+            }
             recurse_through_struct_fields(
                 &mut s.fields,
                 &strike_attrs,
@@ -159,43 +691,137 @@ pub(crate) fn recurse_through_definition(
                 path,
                 s.name.span(),
             );
+            add_each_fields(s, ret);
+            let (defaults, debug_skips) = match take_crate_attr(&mut s.attributes, "sort_fields") {
+                true => {
+                    let order = sort_fields(&mut s.fields);
+                    let defaults = order.iter().map(|&i| defaults.get(i).cloned().flatten());
+                    let debug_skips = order.iter().map(|&i| debug_skips.get(i) == Some(&true));
+                    (defaults.collect(), debug_skips.collect())
+                }
+                false => (defaults, debug_skips),
+            };
+            let types = field_types(&s.fields);
+            inherit_generics(
+                &mut s.generic_params,
+                &mut s.where_clause,
+                (inherited_generics, inherited_where),
+                &types,
+                true,
+            );
+            // The struct given to the macro is left as written
+            if !take_crate_attr(&mut s.attributes, "no_phantom_data") && nested {
+                add_phantom_field(s);
+            }
+            if defaults.iter().any(Option::is_some) {
+                // A derived Default would conflict, e.g. from structstruck::each[derive(Default)]
+                remove_derived(&mut s.attributes, "Default");
+                default_impl(s, &defaults).to_tokens(&mut after);
+            }
+            if debug_skips.contains(&true) && remove_derived(&mut s.attributes, "Debug") {
+                debug_impl(s, &debug_skips).to_tokens(&mut after);
+            }
             if make_pub {
                 s.vis_marker.get_or_insert_with(make_pub_marker);
             }
         }
         Declaration::Enum(e) => {
-            strike_through_attributes(&mut e.attributes, &mut strike_attrs, ret);
             let name = e.name.to_string();
-            let path = &NameHints::from(&name, &mut e.attributes);
+            let conversions = take_crate_attr(&mut e.attributes, "variant_conversions");
+            // Checked before the declarations are replaced by their names
+            let wrapping = e
+                .variants
+                .iter()
+                .map(|(v, _)| wraps_declaration(&v.contents))
+                .collect::<Vec<_>>();
+            let affixes = take_name_affixes(&mut e.attributes, ret);
+            let path = &NameHints::from(&name, &mut e.attributes, ret)
+                .with_affixes(&affixes)
+                .with_own_generics(
+                    &e.generic_params,
+                    &e.where_clause,
+                    (inherited_generics, inherited_where),
+                );
+            // Discriminants (`A = 0`) are expressions, not types, and are left alone
             for (v, _) in &mut e.variants.iter_mut() {
                 let name = v.name.to_string();
                 let path = &path.with_variant_name(&name);
+                let skip_pub = take_crate_attr(&mut v.attributes, "skip_pub_propagation");
                 recurse_through_struct_fields(
                     &mut v.contents,
                     &strike_attrs,
                     ret,
-                    is_plain_pub(&e.vis_marker),
+                    !skip_pub && is_plain_pub(&e.vis_marker),
                     path,
                     v.name.span(),
                 );
             }
+            let types = e
+                .variants
+                .iter()
+                .flat_map(|(v, _)| field_types(&v.contents))
+                .collect::<Vec<_>>();
+            inherit_generics(
+                &mut e.generic_params,
+                &mut e.where_clause,
+                (inherited_generics, inherited_where),
+                &types,
+                false,
+            );
+            if conversions {
+                variant_conversions(e, &wrapping).to_tokens(&mut after);
+            }
             if make_pub {
                 e.vis_marker.get_or_insert_with(make_pub_marker);
             }
         }
         Declaration::Union(u) => {
-            strike_through_attributes(&mut u.attributes, &mut strike_attrs, ret);
             let name = u.name.to_string();
-            let path = &NameHints::from(&name, &mut u.attributes);
+            let affixes = take_name_affixes(&mut u.attributes, ret);
+            let path = &NameHints::from(&name, &mut u.attributes, ret)
+                .with_affixes(&affixes)
+                .with_own_generics(
+                    &u.generic_params,
+                    &u.where_clause,
+                    (inherited_generics, inherited_where),
+                );
             named_struct_fields(&mut u.fields, &strike_attrs, ret, false, path);
+            let types = u
+                .fields
+                .fields
+                .iter()
+                .map(|(f, _)| &f.ty.tokens)
+                .collect::<Vec<_>>();
+            inherit_generics(
+                &mut u.generic_params,
+                &mut u.where_clause,
+                (inherited_generics, inherited_where),
+                &types,
+                false,
+            );
             if make_pub {
                 u.vis_marker.get_or_insert_with(make_pub_marker);
             }
         }
         Declaration::TyDefinition(t) => {
-            strike_through_attributes(&mut t.attributes, &mut strike_attrs, ret);
+            // The inherited ones are already filtered, see StrikeAttr::attr_for
+            t.attributes.retain(|attr| match attr.path.first() {
+                Some(TokenTree::Ident(kw)) if kw == "derive" => {
+                    report_error(
+                        Some(kw.span()),
+                        ret,
+                        "#[derive(…)] is not possible on type aliases, derive on the aliased type instead",
+                    );
+                    false
+                }
+                _ => true,
+            });
             let name = t.name.to_string();
-            let path = &NameHints::from(&name, &mut t.attributes);
+            let affixes = take_name_affixes(&mut t.attributes, ret);
+            // venial 0.5 doesn't parse generic type aliases, the enclosing generics are passed on as they are
+            let path = &NameHints::from(&name, &mut t.attributes, ret)
+                .with_affixes(&affixes)
+                .with_generics(inherited_generics, inherited_where);
             let ttok = mem::take(&mut t.initializer_ty.tokens);
             recurse_through_type_list(
                 &type_tree(&ttok, ret),
@@ -210,15 +836,70 @@ pub(crate) fn recurse_through_definition(
                 t.vis_marker.get_or_insert_with(make_pub_marker);
             }
         }
+        Declaration::Function(f) => {
+            let name = f.name.to_string();
+            let affixes = take_name_affixes(&mut f.attributes, ret);
+            let path = &NameHints::from(&name, &mut f.attributes, ret)
+                .with_affixes(&affixes)
+                .with_generics(f.generic_params.as_ref(), f.where_clause.as_ref());
+            // Qualifiers like async or unsafe are kept by venial, only parameters and return type are touched
+            recurse_through_fn_signature(
+                &f.name,
+                &mut f.params,
+                &mut f.return_ty,
+                is_plain_pub(&f.vis_marker),
+                &strike_attrs,
+                ret,
+                path,
+            );
+        }
         _ => {
             report_error(
                 span,
                 ret,
-                "Unsupported declaration (only struct, enum, and union are allowed)",
+                "Unsupported declaration (only struct, enum, union, type, fn, and impl are allowed)",
             );
             return None;
         }
     }
+    // The nested declarations have been emitted already, so whether they are Copy is known
+    let copy = fields_copy(
+        &parsed,
+        &copy_types(&ret.clone().into_iter().collect::<Vec<_>>()),
+    );
+    let impls = take_setting(&mut parsed, |attrs| take_each_impls(attrs, ret));
+    each_impls(&parsed, &impls).to_tokens(&mut after);
+    if let Some(attributes) = attributes_mut(&mut parsed) {
+        resolve_each_if(attributes, copy, ret);
+        allow_dead_code(attributes);
+        // Settings that only make sense on structs
+        take_crate_attr(attributes, "each_field");
+        take_crate_attr(attributes, "sort_fields");
+        take_crate_attr(attributes, "transparent");
+        take_crate_attr(attributes, "inherit_field_attrs");
+        take_crate_attr(attributes, "no_phantom_data");
+        take_crate_attr(attributes, "accessors");
+        take_crate_attr(attributes, "variant_conversions");
+    }
+    if let Declaration::Enum(e) = &parsed {
+        check_internally_tagged(e, ret);
+    }
+    if let Some(vis) = vis {
+        match &mut parsed {
+            Declaration::Struct(s) => s.vis_marker = vis,
+            Declaration::Enum(e) => e.vis_marker = vis,
+            Declaration::Union(u) => u.vis_marker = vis,
+            Declaration::TyDefinition(t) => t.vis_marker = vis,
+            Declaration::Function(f) => f.vis_marker = vis,
+            _ => (),
+        }
+    }
+    if let Some(alias) = alias {
+        alias_decl(&alias, &parsed, ret).to_tokens(&mut after);
+    }
+    if let (Some(nested), Declaration::Struct(s)) = (accessor, &parsed) {
+        accessor_impl(s, nested).to_tokens(&mut after);
+    }
     if let Declaration::Struct(s) = &mut parsed {
         if let StructFields::Tuple(_) = s.fields {
             if s.tk_semicolon.is_none() {
@@ -226,54 +907,1533 @@ pub(crate) fn recurse_through_definition(
             }
         }
     }
-    parsed.to_tokens(ret);
+    if let Some((kind, name)) = &kind_and_name {
+        if *kind != "fn" {
+            report_name_collisions(
+                name,
+                &ret.clone().into_iter().collect::<Vec<_>>()[ret_start..],
+                ret,
+            );
+        }
+    }
+    if !children_only {
+        // The children have been emitted during recursion, move them behind the declaration
+        let children = match emit_after {
+            true => {
+                let mut all = mem::take(ret).into_iter().collect::<Vec<_>>();
+                let children = all.split_off(ret_start);
+                ret.extend(all);
+                children
+            }
+            false => vec![],
+        };
+        match &original {
+            Some(original) => original.to_tokens(ret),
+            None => {
+                parsed.to_tokens(ret);
+                ret.extend(after);
+            }
+        }
+        ret.extend(children);
+    }
+    if let Some(max) = max_types {
+        let generated = ret.clone().into_iter().skip(ret_start).collect::<Vec<_>>();
+        let exceeded = generated_types(&generated).nth(max);
+        if let Some((_, name)) = exceeded {
+            *ret = mem::take(ret).into_iter().take(ret_start).collect();
+            report_error(
+                Some(name.span()),
+                ret,
+                &format!(
+                    "#[structstruck::max_types({})]: limit exceeded at {}",
+                    max, name
+                ),
+            );
+            return None;
+        }
+    }
+    if let Some(module) = reexport {
+        let generated = ret.clone().into_iter().skip(ret_start).collect::<Vec<_>>();
+        reexport_module(&module, &generated).to_tokens(ret);
+    }
+    if debug {
+        let generated = ret
+            .clone()
+            .into_iter()
+            .skip(ret_start)
+            .collect::<TokenStream>();
+        report_debug_output(ret, span.unwrap_or_else(Span::call_site), &generated);
+    }
     parsed.generic_params().cloned()
 }
 
-fn hack_append_type_decl_semicolon(input_vec: Vec<TokenTree>) -> TokenStream {
-    let is_type_decl = input_vec
+fn attributes_mut(decl: &mut Declaration) -> Option<&mut Vec<Attribute>> {
+    match decl {
+        Declaration::Struct(s) => Some(&mut s.attributes),
+        Declaration::Enum(e) => Some(&mut e.attributes),
+        Declaration::Union(u) => Some(&mut u.attributes),
+        Declaration::TyDefinition(t) => Some(&mut t.attributes),
+        Declaration::Function(f) => Some(&mut f.attributes),
+        _ => None,
+    }
+}
+
+/// Takes a setting from the attributes of `decl` with e.g. [`take_crate_attr`].
+/// Declarations without attributes have the default.
+fn take_setting<T: Default>(
+    decl: &mut Declaration,
+    take: impl FnOnce(&mut Vec<Attribute>) -> T,
+) -> T {
+    attributes_mut(decl).map(take).unwrap_or_default()
+}
+
+/// Appends the fields from `#[structstruck::each_field(…)]`
+fn add_each_fields(s: &mut venial::Struct, ret: &mut TokenStream) {
+    let mut each_fields = vec![];
+    s.attributes.retain(|attr| {
+        if !check_crate_attr(attr, "each_field") {
+            return true;
+        }
+        let tokens = attr
+            .get_value_tokens()
+            .iter()
+            .cloned()
+            .collect::<TokenStream>();
+        match parse_declaration(quote!(struct S { #tokens })) {
+            Ok(Declaration::Struct(venial::Struct {
+                fields: StructFields::Named(n),
+                ..
+            })) if n.fields.len() == 1 => each_fields.extend(
+                n.fields
+                    .iter()
+                    .map(|(f, _)| (f.clone(), attr.tk_hash.span())),
+            ),
+            _ => report_attr_error(
+                attr,
+                ret,
+                "#[structstruck::each_field(…)]: expected a single field, e.g. id: u64",
+            ),
+        }
+        false
+    });
+    let n = match &mut s.fields {
+        StructFields::Named(n) => n,
+        _ => return,
+    };
+    for (field, span) in each_fields {
+        if n.fields.iter().any(|(f, _)| f.name == field.name) {
+            report_error(
+                Some(span),
+                ret,
+                &format!(
+                    "#[structstruck::each_field(…)]: {} already has a field {}",
+                    s.name, field.name
+                ),
+            );
+            continue;
+        }
+        n.fields.push(field, None);
+    }
+}
+
+/// Sorts named fields by name for `#[structstruck::sort_fields]`, returns their previous positions
+fn sort_fields(fields: &mut StructFields) -> Vec<usize> {
+    let n = match fields {
+        StructFields::Named(n) => n,
+        StructFields::Tuple(t) => return (0..t.fields.len()).collect(),
+        StructFields::Unit => return vec![],
+    };
+    let mut sorted = n
+        .fields
         .iter()
-        .any(|t| matches!(t, TokenTree::Ident(kw) if kw == "type"))
-        && input_vec.iter().all(|t| {
-            matches!(t, TokenTree::Ident(kw) if kw == "type")
-                || !matches!(t, TokenTree::Ident(kw) if is_decl_kw(kw))
+        .map(|(f, _)| f.clone())
+        .enumerate()
+        .collect::<Vec<_>>();
+    sorted.sort_by_key(|(_, f)| f.name.to_string().trim_start_matches("r#").to_owned());
+    n.fields = venial::Punctuated::new();
+    sorted
+        .into_iter()
+        .map(|(i, f)| {
+            n.fields.push(f, None);
+            i
+        })
+        .collect()
+}
+
+/// Removes `#[structstruck::default = …]` from the fields, returns the expressions
+fn take_field_defaults(
+    fields: &mut StructFields,
+    ret: &mut TokenStream,
+) -> Vec<Option<Vec<TokenTree>>> {
+    let mut take = |attributes: &mut Vec<Attribute>| {
+        let mut default = None;
+        attributes.retain(|attr| {
+            if !check_crate_attr(attr, "default") {
+                return true;
+            }
+            match &attr.value {
+                AttributeValue::Equals(_, expr) => default = Some(expr.clone()),
+                _ => report_error(
+                    Some(attr.tk_hash.span()),
+                    ret,
+                    "#[structstruck::default = …]: expected = expression",
+                ),
+            }
+            false
         });
-    let input = match is_type_decl {
-        true => input_vec
-            .into_iter()
-            .chain(once(TokenTree::Punct(Punct::new(';', Spacing::Alone))))
+        default
+    };
+    match fields {
+        StructFields::Unit => vec![],
+        StructFields::Named(n) => n
+            .fields
+            .iter_mut()
+            .map(|(f, _)| take(&mut f.attributes))
             .collect(),
-        false => input_vec.into_iter().collect(),
+        StructFields::Tuple(t) => t
+            .fields
+            .iter_mut()
+            .map(|(f, _)| take(&mut f.attributes))
+            .collect(),
+    }
+}
+
+/// `impl Default` using the expressions from `#[structstruck::default = …]`
+/// and `Default::default()` for all other fields
+fn default_impl(s: &venial::Struct, defaults: &[Option<Vec<TokenTree>>]) -> TokenStream {
+    let expr = |i: usize| match defaults.get(i) {
+        Some(Some(expr)) => expr.iter().cloned().collect(),
+        _ => quote!(::core::default::Default::default()),
     };
-    input
+    let body = match &s.fields {
+        StructFields::Named(n) => {
+            let fields = n.fields.iter().enumerate().map(|(i, (f, _))| {
+                let (name, expr) = (&f.name, expr(i));
+                quote!(#name: #expr,)
+            });
+            quote!({ #(#fields)* })
+        }
+        StructFields::Tuple(t) => {
+            let fields = (0..t.fields.len()).map(expr);
+            quote!(( #(#fields,)* ))
+        }
+        StructFields::Unit => quote!(),
+    };
+    let name = &s.name;
+    let generics = s.generic_params.as_ref().map(impl_params);
+    let args = s.generic_params.as_ref().map(generic_args);
+    // Like the derive, require Default of all type parameters
+    let where_clause = bound_type_params(s, quote!(::core::default::Default));
+    quote! {
+        #[automatically_derived]
+        #[allow(clippy::all)]
+        impl #generics ::core::default::Default for #name #args #where_clause {
+            fn default() -> Self {
+                Self #body
+            }
+        }
+    }
 }
 
-pub(crate) fn make_pub_marker() -> venial::VisMarker {
-    venial::VisMarker {
-        tk_token1: TokenTree::Ident(Ident::new("pub", Span::mixed_site())),
-        tk_token2: None,
+/// The where clause of `s`, plus `T: #bound` for each of its type parameters
+fn bound_type_params(s: &venial::Struct, bound: TokenStream) -> TokenStream {
+    let mut where_clause = s
+        .where_clause
+        .to_token_stream()
+        .into_iter()
+        .collect::<Vec<_>>();
+    let bounded = s
+        .generic_params
+        .iter()
+        .flat_map(|g| g.params.iter())
+        .filter(|(p, _)| p.tk_prefix.is_none());
+    for (p, _) in bounded {
+        match where_clause.last() {
+            None => where_clause.push(TokenTree::Ident(Ident::new("where", p.name.span()))),
+            Some(TokenTree::Punct(p)) if p.as_char() == ',' => (),
+            Some(_) => where_clause.push(TokenTree::Punct(Punct::new(',', Spacing::Alone))),
+        }
+        let name = &p.name;
+        where_clause.extend(quote!(#name: #bound,));
     }
+    where_clause.into_iter().collect()
 }
 
-pub(crate) fn is_plain_pub(vis_marker: &Option<venial::VisMarker>) -> bool {
-    match vis_marker {
-        Some(venial::VisMarker {
-            tk_token1: TokenTree::Ident(i),
-            tk_token2: None,
-        }) if i.to_string() == "pub" => true,
-        _ => false,
+/// Which fields are marked `#[structstruck::debug_skip]`
+fn take_debug_skips(fields: &mut StructFields) -> Vec<bool> {
+    let take = |attributes: &mut Vec<Attribute>| take_crate_attr(attributes, "debug_skip");
+    match fields {
+        StructFields::Unit => vec![],
+        StructFields::Named(n) => n
+            .fields
+            .iter_mut()
+            .map(|(f, _)| take(&mut f.attributes))
+            .collect(),
+        StructFields::Tuple(t) => t
+            .fields
+            .iter_mut()
+            .map(|(f, _)| take(&mut f.attributes))
+            .collect(),
     }
 }
 
-fn move_out_inner_attrs(input: TokenStream) -> TokenStream {
-    let mut prefix = vec![];
-    let mut ret = vec![];
-    for e in input {
-        match e {
-            TokenTree::Group(g) if g.delimiter() == Delimiter::Brace => {
-                let mut tt: Vec<TokenTree> = vec![];
-                let gt = g.stream().into_iter().collect::<Vec<_>>();
-                let mut gt = &gt[..];
+/// Removes a trait from all `#[derive(…)]`s, returns whether it was derived
+fn remove_derived(attributes: &mut Vec<Attribute>, name: &str) -> bool {
+    let mut found = false;
+    attributes.retain_mut(|attr| {
+        if !derived_traits(attr)
+            .iter()
+            .any(|tr| tr.rsplit(' ').next() == Some(name))
+        {
+            return true;
+        }
+        found = true;
+        let keep = |list: &[TokenTree]| {
+            list.split(|t| matches!(t, TokenTree::Punct(p) if p.as_char() == ','))
+                .filter(|tr| {
+                    !tr.is_empty() && !matches!(tr.last(), Some(TokenTree::Ident(i)) if i == name)
+                })
+                .flat_map(|tr| {
+                    tr.iter()
+                        .cloned()
+                        .chain(once(TokenTree::Punct(Punct::new(',', Spacing::Alone))))
+                })
+                .collect::<Vec<_>>()
+        };
+        let list = match (&mut attr.path[..], &mut attr.value) {
+            (_, AttributeValue::Group(_, list)) => list,
+            // From structstruck::each, see strike_through_attributes
+            ([_, TokenTree::Group(g)], AttributeValue::Empty) => {
+                let list = keep(&g.stream().into_iter().collect::<Vec<_>>());
+                let mut group = Group::new(g.delimiter(), list.iter().cloned().collect());
+                group.set_span(g.span());
+                *g = group;
+                return !list.is_empty();
+            }
+            _ => return true,
+        };
+        *list = keep(list);
+        !list.is_empty()
+    });
+    found
+}
+
+/// `impl Debug` like the derived one, but without the fields marked `#[structstruck::debug_skip]`
+fn debug_impl(s: &venial::Struct, skips: &[bool]) -> TokenStream {
+    let shown = |i: usize| !skips.get(i).copied().unwrap_or(false);
+    let name = &s.name;
+    let name_str = name.to_string();
+    let body = match &s.fields {
+        StructFields::Named(n) => {
+            let fields =
+                n.fields
+                    .iter()
+                    .enumerate()
+                    .filter(|&(i, _)| shown(i))
+                    .map(|(_, (f, _))| {
+                        let (field, field_str) = (&f.name, f.name.to_string());
+                        quote!(.field(#field_str, &self.#field))
+                    });
+            quote!(f.debug_struct(#name_str) #(#fields)* .finish_non_exhaustive())
+        }
+        StructFields::Tuple(t) => {
+            let fields = (0..t.fields.len()).filter(|&i| shown(i)).map(|i| {
+                let i = proc_macro2::Literal::usize_unsuffixed(i);
+                quote!(.field(&self.#i))
+            });
+            quote!(f.debug_tuple(#name_str) #(#fields)* .finish())
+        }
+        StructFields::Unit => quote!(f.write_str(#name_str)),
+    };
+    let generics = s.generic_params.as_ref().map(impl_params);
+    let args = s.generic_params.as_ref().map(generic_args);
+    // Like the derive, require Debug of all type parameters
+    let where_clause = bound_type_params(s, quote!(::core::fmt::Debug));
+    quote! {
+        #[automatically_derived]
+        #[allow(clippy::all)]
+        impl #generics ::core::fmt::Debug for #name #args #where_clause {
+            fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                #body
+            }
+        }
+    }
+}
+
+/// `fn value(&self) -> &T` for newtypes, named after the type if it was declared in place
+fn accessor_impl(s: &venial::Struct, nested: bool) -> TokenStream {
+    let ty = match &s.fields {
+        StructFields::Tuple(t) => match t.fields.iter().next() {
+            Some((f, _)) => f.ty.tokens.iter().cloned().collect::<TokenStream>(),
+            None => return quote!(),
+        },
+        _ => return quote!(),
+    };
+    let fn_name = ty.clone().into_iter().find_map(|t| match t {
+        TokenTree::Ident(name) if nested => {
+            let name_str = name.to_string();
+            let fn_name = snake_case(name_str.strip_prefix("r#").unwrap_or(&name_str));
+            Some(match is_keyword(&fn_name) {
+                // struct Meters(struct Type(f64)) gets fn r#type
+                true => Ident::new_raw(&fn_name, name.span()),
+                false => Ident::new(&fn_name, name.span()),
+            })
+        }
+        _ => None,
+    });
+    let fn_name = fn_name.unwrap_or_else(|| Ident::new("value", s.name.span()));
+    let name = &s.name;
+    let generics = s.generic_params.as_ref().map(impl_params);
+    let args = s.generic_params.as_ref().map(generic_args);
+    let where_clause = &s.where_clause;
+    let vis = &s.vis_marker;
+    quote! {
+        #[automatically_derived]
+        #[allow(dead_code)]
+        #[allow(clippy::all)]
+        impl #generics #name #args #where_clause {
+            #vis fn #fn_name(&self) -> &#ty {
+                &self.0
+            }
+        }
+    }
+}
+
+/// Whether the fields are a single tuple field with a declaration in place, like `Foo(struct Bar {…})`
+fn wraps_declaration(fields: &StructFields) -> bool {
+    match fields {
+        StructFields::Tuple(t) if t.fields.len() == 1 => t.fields.iter().any(|(f, _)| {
+            // Not Box<struct Bar {…}>, that's only wrapped
+            let tree = type_tree(&f.ty.tokens, &mut TokenStream::new());
+            let wraps = decl_kws(&tree).next().is_some();
+            wraps
+        }),
+        _ => false,
+    }
+}
+
+/// `From<Inner> for Enum` and `TryFrom<Enum> for Inner` for the variants that wrap a declaration
+fn variant_conversions(e: &venial::Enum, wrapping: &[bool]) -> TokenStream {
+    let name = &e.name;
+    let generics = e.generic_params.as_ref().map(impl_params);
+    let args = e.generic_params.as_ref().map(generic_args);
+    let where_clause = &e.where_clause;
+    e.variants
+        .iter()
+        .zip(wrapping)
+        .filter_map(|((v, _), &wrapping)| {
+            let ty = match &v.contents {
+                StructFields::Tuple(t) if wrapping => t.fields.iter().next()?.0.ty.tokens.clone(),
+                _ => return None,
+            };
+            let ty = ty.into_iter().collect::<TokenStream>();
+            let variant = &v.name;
+            Some(quote! {
+                #[automatically_derived]
+                #[allow(clippy::all)]
+                impl #generics ::core::convert::From<#ty> for #name #args #where_clause {
+                    fn from(value: #ty) -> Self {
+                        Self::#variant(value)
+                    }
+                }
+                #[automatically_derived]
+                #[allow(clippy::all)]
+                impl #generics ::core::convert::TryFrom<#name #args> for #ty #where_clause {
+                    type Error = #name #args;
+                    #[allow(unreachable_patterns)]
+                    fn try_from(value: #name #args) -> ::core::result::Result<Self, Self::Error> {
+                        match value {
+                            #name::#variant(inner) => ::core::result::Result::Ok(inner),
+                            other => ::core::result::Result::Err(other),
+                        }
+                    }
+                }
+            })
+        })
+        .collect()
+}
+
+/// Generic parameters that are declared but not used by any field make for a compile error.
+/// Appending a `PhantomData` field that uses them keeps the parameter list intact.
+fn add_phantom_field(s: &mut venial::Struct) {
+    let params: Vec<GenericParam> = match &s.generic_params {
+        Some(generics) => generics.params.iter().map(|(p, _)| p.clone()).collect(),
+        None => vec![],
+    };
+    if let StructFields::Unit = s.fields {
+        return;
+    }
+    let field_types = field_types(&s.fields);
+    let markers = params
+        .iter()
+        .filter(|p| !field_types.iter().any(|ty| uses_ident(ty, &p.name)))
+        .filter_map(|p| {
+            let name = &p.name;
+            match &p.tk_prefix {
+                Some(tick @ TokenTree::Punct(_)) => Some(quote!(&#tick #name ())),
+                Some(_) => None, // const generics don't need (and can't have) a marker
+                None => Some(quote!(#name)),
+            }
+        })
+        .collect::<Vec<_>>();
+    if markers.is_empty() {
+        return;
+    }
+    let ty = venial::TyExpr {
+        tokens: quote!(::core::marker::PhantomData<(#(#markers,)*)>)
+            .into_iter()
+            .collect(),
+    };
+    match &mut s.fields {
+        StructFields::Unit => unreachable!(),
+        StructFields::Named(n) => n.fields.push(
+            venial::NamedField {
+                attributes: vec![],
+                vis_marker: None,
+                name: Ident::new("_phantom", Span::mixed_site()),
+                tk_colon: Punct::new(':', Spacing::Alone),
+                ty,
+            },
+            None,
+        ),
+        StructFields::Tuple(t) => t.fields.push(
+            venial::TupleField {
+                attributes: vec![],
+                vis_marker: None,
+                ty,
+            },
+            None,
+        ),
+    }
+}
+
+/// Nested declarations without generic parameters of their own get those of the enclosing
+/// declaration that their fields use. Declarations further in have already been handled,
+/// so `Inner<T>` counts as a use of `T`, and parameters are threaded through any depth.
+/// Predicates of the where clause come along if they only name parameters that do.
+/// Enums and unions get no field from [`add_phantom_field`], so `phantom` is false for them
+/// and bounds naming parameters they don't use are dropped instead.
+fn inherit_generics(
+    generics: &mut Option<GenericParamList>,
+    where_clause: &mut Option<WhereClause>,
+    (inherited, inherited_where): (Option<&GenericParamList>, Option<&WhereClause>),
+    types: &[&Vec<TokenTree>],
+    phantom: bool,
+) {
+    let inherited = match (&generics, inherited) {
+        (None, Some(inherited)) => inherited,
+        _ => return,
+    };
+    let params = inherited.params.iter().map(|(p, _)| p).collect::<Vec<_>>();
+    let mut used = params
+        .iter()
+        .filter(|p| types.iter().any(|ty| uses_ident(ty, &p.name)))
+        .map(|p| p.name.to_string())
+        .collect::<HashSet<_>>();
+    if used.is_empty() {
+        return;
+    }
+    // The parameters each predicate names, e.g. T and U for `Vec<T>: From<U>`
+    let predicates = inherited_where
+        .iter()
+        .flat_map(|w| w.items.iter())
+        .map(|(item, _)| {
+            let tokens = item.to_token_stream().into_iter().collect::<Vec<_>>();
+            let named = params
+                .iter()
+                .filter(|p| uses_ident(&tokens, &p.name))
+                .map(|p| p.name.to_string())
+                .collect::<HashSet<_>>();
+            (item, named)
+        })
+        .collect::<Vec<_>>();
+    // Bounds are kept as they are (`T: Clone + ?Sized`), but may name other parameters,
+    // e.g. `T: Into<U>` or `'a: 'b`. Those have to come along, too, unless there is no PhantomData for them.
+    // Predicates don't pull in parameters, they are only kept if all they name is there anyway.
+    loop {
+        let more = params
+            .iter()
+            .filter(|p| !used.contains(&p.name.to_string()))
+            .filter(|p| {
+                params.iter().any(|u| {
+                    used.contains(&u.name.to_string())
+                        && u.bound
+                            .as_ref()
+                            .is_some_and(|b| uses_ident(&b.tokens, &p.name))
+                })
+            })
+            .map(|p| p.name.to_string())
+            .collect::<Vec<_>>();
+        if more.is_empty() || !phantom {
+            break;
+        }
+        used.extend(more);
+    }
+    let mut list = inherited.clone();
+    list.params = Default::default();
+    for p in &params {
+        if used.contains(&p.name.to_string()) {
+            let mut p = (*p).clone();
+            if let Some(bound) = &p.bound {
+                let tokens = trim_bound(&bound.tokens, &params, &used);
+                p.bound = match tokens.is_empty() {
+                    true => None,
+                    false => Some(GenericBound {
+                        tokens,
+                        ..bound.clone()
+                    }),
+                };
+            }
+            list.params.push(p, None);
+        }
+    }
+    *generics = Some(list);
+    let kept = predicates
+        .iter()
+        .filter(|(_, named)| !named.is_empty() && named.iter().all(|n| used.contains(n)))
+        .collect::<Vec<_>>();
+    if let (None, Some(inherited_where), false) = (&where_clause, inherited_where, kept.is_empty())
+    {
+        let mut clause = inherited_where.clone();
+        clause.items = Default::default();
+        for (item, _) in kept {
+            clause.items.push((*item).clone(), None);
+        }
+        *where_clause = Some(clause);
+    }
+}
+
+/// Drops the parts of a bound (`Clone + Into<U>`) that name parameters which aren't `used`
+fn trim_bound(
+    bound: &[TokenTree],
+    params: &[&GenericParam],
+    used: &HashSet<String>,
+) -> Vec<TokenTree> {
+    let mut depth = 0;
+    let mut parts = vec![vec![]];
+    for (i, t) in bound.iter().enumerate() {
+        match t {
+            TokenTree::Punct(p) if p.as_char() == '<' => depth += 1,
+            TokenTree::Punct(p) if p.as_char() == '>' && !is_arrow(bound, i) => depth -= 1,
+            TokenTree::Punct(p) if p.as_char() == '+' && depth == 0 => {
+                parts.push(vec![]);
+                continue;
+            }
+            _ => (),
+        }
+        parts.last_mut().unwrap().push(t.clone());
+    }
+    let plus = TokenTree::Punct(Punct::new('+', Spacing::Alone));
+    parts
+        .into_iter()
+        .filter(|part| {
+            params
+                .iter()
+                .all(|p| used.contains(&p.name.to_string()) || !uses_ident(part, &p.name))
+        })
+        .collect::<Vec<_>>()
+        .join(&plus)
+}
+
+fn field_types(fields: &StructFields) -> Vec<&Vec<TokenTree>> {
+    match fields {
+        StructFields::Unit => vec![],
+        StructFields::Named(n) => n.fields.iter().map(|(f, _)| &f.ty.tokens).collect(),
+        StructFields::Tuple(t) => t.fields.iter().map(|(f, _)| &f.ty.tokens).collect(),
+    }
+}
+
+fn uses_ident(tokens: &[TokenTree], name: &Ident) -> bool {
+    tokens.iter().any(|t| match t {
+        TokenTree::Ident(i) => i == name,
+        TokenTree::Group(g) => uses_ident(&g.stream().into_iter().collect::<Vec<_>>(), name),
+        _ => false,
+    })
+}
+
+/// Defaults and bounds of generic parameters (`struct Foo<T = struct Bar {}>`, `fn f<T: Into<struct Arg {}>>`)
+/// may contain declarations, too.
+/// These are handled before parsing, name hints are made from the declaration and parameter name.
+/// Defaults of const parameters are expressions, usually in a `{ block }`, and are left as they are.
+fn recurse_through_generic_params(
+    input: Vec<TokenTree>,
+    strike_attrs: &[StrikeAttr],
+    ret: &mut TokenStream,
+) -> Vec<TokenTree> {
+    let (name, open, close) = match generic_param_list(&input) {
+        Some(list) => list,
+        None => return input,
+    };
+    if !input[open..close]
+        .iter()
+        .any(|t| matches!(t, TokenTree::Ident(kw) if is_decl_kw(kw)))
+    {
+        return input;
+    }
+    let params = type_tree(&input[open + 1..close], ret);
+    let path = NameHints {
+        long: true,
+        parent_name: &name,
+        ..Default::default()
+    };
+    let mut out = input[..=open].to_vec();
+    let mut tok = &params[..];
+    loop {
+        let end = tok.iter().position(|t| get_tt_punct(t, ',').is_some());
+        let current = &tok[..end.unwrap_or(tok.len())];
+        let is_const =
+            matches!(current.first(), Some(TypeTree::Token(TokenTree::Ident(kw))) if kw == "const");
+        let is_lifetime = matches!(current.first(), Some(t) if get_tt_punct(t, '\'').is_some());
+        let param = current.iter().find_map(|t| match t {
+            TypeTree::Token(TokenTree::Ident(i)) => Some(i),
+            _ => None,
+        });
+        let param_name = param.map(|p| p.to_string()).unwrap_or_default();
+        let span = param.map_or_else(Span::call_site, |p| p.span());
+        let path = path.with_field_name(&param_name);
+        let mut recurse = |tok: &[TypeTree], out: &mut Vec<TokenTree>| {
+            recurse_through_type_list(
+                tok,
+                strike_attrs,
+                ret,
+                &Some(path.get_name_hint(None, span)),
+                false,
+                out,
+                &path,
+            )
+        };
+        let eq = current
+            .iter()
+            .position(|t| get_tt_punct(t, '=').is_some())
+            .filter(|_| !is_const);
+        let bounds_end = eq.unwrap_or(current.len());
+        // Bounds, e.g. T: Into<struct Arg {…}>, but not the type of a const parameter
+        let colon = current[..bounds_end]
+            .iter()
+            .position(|t| get_tt_punct(t, ':').is_some())
+            .filter(|_| !is_const && !is_lifetime);
+        match colon {
+            Some(colon) => {
+                un_tree_type(&current[..=colon], &mut out);
+                recurse(&current[colon + 1..bounds_end], &mut out);
+            }
+            None => un_tree_type(&current[..bounds_end], &mut out),
+        }
+        if let Some(eq) = eq {
+            un_tree_type(&current[eq..=eq], &mut out);
+            recurse(&current[eq + 1..], &mut out);
+        }
+        match end {
+            Some(comma) => {
+                un_tree_type(&tok[comma..=comma], &mut out);
+                tok = &tok[comma + 1..];
+            }
+            None => break,
+        }
+    }
+    out.extend_from_slice(&input[close..]);
+    out
+}
+
+/// Parameter types are named after the parameter, the return type after the function
+fn recurse_through_fn_signature(
+    name: &Ident,
+    params: &mut venial::Punctuated<venial::FnParam>,
+    return_ty: &mut Option<venial::TyExpr>,
+    pub_hint: bool,
+    strike_attrs: &[StrikeAttr],
+    ret: &mut TokenStream,
+    path: &NameHints,
+) {
+    for (param, _) in params.iter_mut() {
+        if let venial::FnParam::Typed(param) = param {
+            let param_name = param.name.to_string();
+            let path = &path.with_field_name(&param_name);
+            let name_hint = path.get_name_hint(None, param.name.span());
+            let ttok = mem::take(&mut param.ty.tokens);
+            recurse_through_type_list(
+                &type_tree(&ttok, ret),
+                strike_attrs,
+                ret,
+                &Some(name_hint),
+                pub_hint,
+                &mut param.ty.tokens,
+                path,
+            );
+        }
+    }
+    if let Some(return_ty) = return_ty {
+        let name_hint = path.get_name_hint(None, name.span());
+        let ttok = mem::take(&mut return_ty.tokens);
+        recurse_through_type_list(
+            &type_tree(&ttok, ret),
+            strike_attrs,
+            ret,
+            &Some(name_hint),
+            pub_hint,
+            &mut return_ty.tokens,
+            path,
+        );
+    }
+}
+
+/// venial takes the first `{…}` after the `->` of a function as its body, so a declaration in the return type
+/// (`fn f() -> struct { … } { body }`) is put into an undelimited group first.
+/// The body is the last token, and a where clause comes right before it.
+fn group_fn_return(input: Vec<TokenTree>) -> Vec<TokenTree> {
+    let kw = match input
+        .iter()
+        .position(|t| matches!(t, TokenTree::Ident(kw) if kw == "fn"))
+    {
+        Some(kw) => kw,
+        None => return input,
+    };
+    let mut depth = 0;
+    let params = input
+        .iter()
+        .enumerate()
+        .skip(kw)
+        .position(|(i, t)| match t {
+            TokenTree::Punct(p) if p.as_char() == '<' => {
+                depth += 1;
+                false
+            }
+            TokenTree::Punct(p) if p.as_char() == '>' && !is_arrow(&input, i) => {
+                depth -= 1;
+                false
+            }
+            TokenTree::Group(g) => depth == 0 && g.delimiter() == Delimiter::Parenthesis,
+            _ => false,
+        });
+    let start = match params.map(|params| kw + params) {
+        Some(params)
+            if matches!(input.get(params + 2), Some(TokenTree::Punct(p)) if p.as_char() == '>')
+                && is_arrow(&input, params + 2) =>
+        {
+            params + 3
+        }
+        _ => return input,
+    };
+    let mut end = match input.last() {
+        Some(TokenTree::Group(g)) if g.delimiter() == Delimiter::Brace => input.len() - 1,
+        Some(TokenTree::Punct(p)) if p.as_char() == ';' => input.len() - 1,
+        _ => input.len(),
+    };
+    if let Some(clause) = input[start..end]
+        .iter()
+        .rev()
+        .take_while(|t| !matches!(t, TokenTree::Group(g) if g.delimiter() == Delimiter::Brace))
+        .position(|t| matches!(t, TokenTree::Ident(kw) if kw == "where"))
+    {
+        end -= clause + 1;
+    }
+    if start >= end {
+        return input;
+    }
+    let mut input = input;
+    let tail = input.split_off(end);
+    let ty = input.split_off(start);
+    input.push(TokenTree::Group(Group::new(
+        Delimiter::None,
+        ty.into_iter().collect(),
+    )));
+    input.extend(tail);
+    input
+}
+
+/// Undoes [`group_fn_return`] after parsing
+fn ungroup_fn_return(f: &mut venial::Function) {
+    if let Some(return_ty) = &mut f.return_ty {
+        if let [TokenTree::Group(g)] = &return_ty.tokens[..] {
+            if g.delimiter() == Delimiter::None {
+                return_ty.tokens = g.stream().into_iter().collect();
+            }
+        }
+    }
+}
+
+/// In `impl` blocks, only the signatures of methods may contain declarations.
+/// They are emitted before the `impl`, which is left alone otherwise.
+/// Returns whether the input was an `impl` block (and has been emitted).
+fn recurse_through_impl(
+    input: &[TokenTree],
+    strike_attrs: &[StrikeAttr],
+    ret: &mut TokenStream,
+) -> bool {
+    let body = match input.last() {
+        Some(TokenTree::Group(body)) if body.delimiter() == Delimiter::Brace => body,
+        _ => return false,
+    };
+    if item_kind(input) != "impl" {
+        return false;
+    }
+    // Items end with a ;, the body of a method or a macro!{…}, or they are an #![inner] attribute
+    let mut items = vec![];
+    let mut current = vec![];
+    for t in body.stream() {
+        let ends = match &t {
+            TokenTree::Punct(p) => p.as_char() == ';',
+            TokenTree::Group(g) if g.delimiter() == Delimiter::Brace => {
+                (item_kind(&current) == "fn" && at_fn_body(&current))
+                    || matches!(current.last(), Some(TokenTree::Punct(p)) if p.as_char() == '!')
+            }
+            TokenTree::Group(g) if g.delimiter() == Delimiter::Bracket => matches!(
+                &current[..],
+                [TokenTree::Punct(hash), TokenTree::Punct(bang)] if hash.as_char() == '#' && bang.as_char() == '!'
+            ),
+            _ => false,
+        };
+        current.push(t);
+        if ends {
+            items.push(mem::take(&mut current));
+        }
+    }
+    items.push(current);
+    let mut new_body = TokenStream::new();
+    for item in items {
+        let method = match item_kind(&item).as_str() {
+            "fn" => parse_declaration(group_fn_return(item.clone()).into_iter().collect()).ok(),
+            _ => None,
+        };
+        match method {
+            Some(Declaration::Function(mut f)) => {
+                ungroup_fn_return(&mut f);
+                let name = f.name.to_string();
+                let affixes = take_name_affixes(&mut f.attributes, ret);
+                let path = &NameHints::from(&name, &mut f.attributes, ret)
+                    .with_affixes(&affixes)
+                    .with_generics(f.generic_params.as_ref(), f.where_clause.as_ref());
+                recurse_through_fn_signature(
+                    &f.name,
+                    &mut f.params,
+                    &mut f.return_ty,
+                    is_plain_pub(&f.vis_marker),
+                    strike_attrs,
+                    ret,
+                    path,
+                );
+                f.to_tokens(&mut new_body);
+            }
+            _ => new_body.extend(item),
+        }
+    }
+    let mut new_group = Group::new(Delimiter::Brace, new_body);
+    new_group.set_span(body.span());
+    ret.extend(input[..input.len() - 1].iter().cloned());
+    ret.extend(once(TokenTree::Group(new_group)));
+    true
+}
+
+/// Whether a `{…}` after the tokens of a fn item is its body,
+/// not the body of a declaration in its return type like `-> Result<struct { … }, E>`
+fn at_fn_body(item: &[TokenTree]) -> bool {
+    let mut depth = 0;
+    let mut declaring = false;
+    for (i, t) in item.iter().enumerate() {
+        match t {
+            TokenTree::Punct(p) if p.as_char() == '<' => depth += 1,
+            TokenTree::Punct(p) if p.as_char() == '>' && !is_arrow(item, i) => depth -= 1,
+            TokenTree::Ident(kw)
+                if depth == 0 && ["struct", "enum", "union"].contains(&&*kw.to_string()) =>
+            {
+                declaring = true
+            }
+            // The body of a declaration, or the fields of a tuple struct
+            TokenTree::Group(g) if depth == 0 && g.delimiter() != Delimiter::Bracket => {
+                declaring = false
+            }
+            _ => (),
+        }
+    }
+    depth == 0 && !declaring
+}
+
+/// `const` and `static` items may contain declarations in their type, but not in their initializer.
+/// Returns whether the input was such an item (and has been emitted).
+fn recurse_through_const_item(
+    input: &[TokenTree],
+    strike_attrs: &[StrikeAttr],
+    ret: &mut TokenStream,
+) -> bool {
+    let mut pos = 0;
+    let mut is_pub = false;
+    loop {
+        match &input[pos..] {
+            [TokenTree::Punct(hash), TokenTree::Group(_), ..] if hash.as_char() == '#' => pos += 2,
+            [TokenTree::Ident(pb), TokenTree::Group(g), ..]
+                if pb == "pub" && g.delimiter() == Delimiter::Parenthesis =>
+            {
+                pos += 2
+            }
+            [TokenTree::Ident(pb), ..] if pb == "pub" => {
+                is_pub = true;
+                pos += 1
+            }
+            _ => break,
+        }
+    }
+    let (name, ty_start) = match &input[pos..] {
+        [TokenTree::Ident(kw), TokenTree::Ident(m), TokenTree::Ident(name), TokenTree::Punct(c), ..]
+            if kw == "static" && m == "mut" && c.as_char() == ':' =>
+        {
+            (name, pos + 4)
+        }
+        [TokenTree::Ident(kw), TokenTree::Ident(name), TokenTree::Punct(c), ..]
+            if (kw == "const" || kw == "static") && c.as_char() == ':' =>
+        {
+            (name, pos + 3)
+        }
+        _ => return false,
+    };
+    let mut depth = 0;
+    let ty_end = input[ty_start..].iter().position(|t| match t {
+        TokenTree::Punct(p) if p.as_char() == '<' => {
+            depth += 1;
+            false
+        }
+        TokenTree::Punct(p) if p.as_char() == '>' => {
+            depth -= 1;
+            false
+        }
+        TokenTree::Punct(p) => depth == 0 && (p.as_char() == '=' || p.as_char() == ';'),
+        _ => false,
+    });
+    let ty_end = ty_start + ty_end.unwrap_or(input.len() - ty_start);
+    // SCREAMING_CASE makes for bad type names
+    let name_str = match name.to_string() {
+        n if n.chars().any(char::is_lowercase) => n,
+        n => n.to_lowercase(),
+    };
+    let path = NameHints {
+        parent_name: &name_str,
+        ..Default::default()
+    };
+    let name_hint = (name_str != "_").then(|| path.get_name_hint(None, name.span()));
+    let mut ty = vec![];
+    recurse_through_type_list(
+        &type_tree(&input[ty_start..ty_end], ret),
+        strike_attrs,
+        ret,
+        &name_hint,
+        is_pub,
+        &mut ty,
+        &path,
+    );
+    ret.extend(
+        input[..ty_start]
+            .iter()
+            .cloned()
+            .chain(ty)
+            .chain(input[ty_end..].iter().cloned()),
+    );
+    true
+}
+
+/// What kind of item the tokens are, for error messages, e.g. `impl` or `extern block`
+fn item_kind(input: &[TokenTree]) -> String {
+    let mut idents = input.iter().filter_map(|t| match t {
+        TokenTree::Ident(kw) if !["pub", "unsafe", "async", "const"].iter().any(|q| kw == q) => {
+            Some(kw.to_string())
+        }
+        _ => None,
+    });
+    match idents.next() {
+        Some(kw) if kw == "extern" => match idents.next() {
+            Some(krate) if krate == "crate" => "extern crate".to_owned(),
+            Some(f) if f == "fn" => f,
+            _ => "extern block (declare the types outside of it)".to_owned(),
+        },
+        Some(kw) => kw,
+        None => "nothing".to_owned(),
+    }
+}
+
+/// The name of the declaration and the positions of the `<` and `>` around its generic parameters
+fn generic_param_list(input: &[TokenTree]) -> Option<(String, usize, usize)> {
+    let (name, pos) = decl_name(input)?;
+    let open = match input.get(pos + 1) {
+        Some(TokenTree::Punct(open)) if open.as_char() == '<' => pos + 1,
+        _ => return None,
+    };
+    let mut depth = 0;
+    let close = input[open..].iter().enumerate().position(|(i, t)| {
+        match t {
+            TokenTree::Punct(p) if p.as_char() == '<' => depth += 1,
+            TokenTree::Punct(p) if p.as_char() == '>' && !is_arrow(input, open + i) => depth -= 1,
+            _ => (),
+        }
+        depth == 0
+    })?;
+    Some((name, open, open + close))
+}
+
+/// venial 0.5 panics on defaults of generic parameters (`struct Foo<T = u8, const N: usize = 1>`),
+/// so they are taken out before parsing and put back by [`restore_generic_defaults`]
+fn take_generic_defaults(
+    input: Vec<TokenTree>,
+    ret: &mut TokenStream,
+) -> (Vec<TokenTree>, Vec<(String, Vec<TokenTree>)>) {
+    let (open, close) = match generic_param_list(&input) {
+        Some((_, open, close)) => (open, close),
+        None => return (input, vec![]),
+    };
+    let params = type_tree(&input[open + 1..close], ret);
+    let mut out = input[..=open].to_vec();
+    let mut defaults = vec![];
+    let mut tok = &params[..];
+    loop {
+        let end = tok.iter().position(|t| get_tt_punct(t, ',').is_some());
+        let current = &tok[..end.unwrap_or(tok.len())];
+        let name = current.iter().find_map(|t| match t {
+            TypeTree::Token(TokenTree::Ident(i)) if i != "const" => Some(i.to_string()),
+            _ => None,
+        });
+        let eq = current.iter().position(|t| get_tt_punct(t, '=').is_some());
+        match (name, eq) {
+            (Some(name), Some(eq)) => {
+                un_tree_type(&current[..eq], &mut out);
+                let mut default = vec![];
+                un_tree_type(&current[eq..], &mut default);
+                if default.len() == 1 {
+                    report_error(
+                        Some(default[0].span()),
+                        ret,
+                        &format!("Expected a default for generic parameter {} after =", name),
+                    );
+                }
+                defaults.push((name, default));
+            }
+            _ => un_tree_type(current, &mut out),
+        }
+        match end {
+            Some(comma) => {
+                un_tree_type(&tok[comma..=comma], &mut out);
+                tok = &tok[comma + 1..];
+            }
+            None => break,
+        }
+    }
+    out.extend_from_slice(&input[close..]);
+    (out, defaults)
+}
+
+/// Puts the defaults taken by [`take_generic_defaults`] back after the bounds
+fn restore_generic_defaults(decl: &mut Declaration, defaults: &[(String, Vec<TokenTree>)]) {
+    let generics = match decl {
+        Declaration::Struct(s) => &mut s.generic_params,
+        Declaration::Enum(e) => &mut e.generic_params,
+        Declaration::Union(u) => &mut u.generic_params,
+        // Not allowed, but rustc can say so
+        Declaration::Function(f) => &mut f.generic_params,
+        _ => return,
+    };
+    for (param, _) in generics.iter_mut().flat_map(|g| g.params.iter_mut()) {
+        let default = defaults.iter().find(|(name, _)| param.name == name);
+        match (&mut param.bound, default.map(|(_, d)| d.split_first())) {
+            (Some(bound), Some(Some((eq, default)))) => {
+                bound.tokens.push(eq.clone());
+                bound.tokens.extend_from_slice(default);
+            }
+            // Hack a bit: The = takes the place of the colon
+            (None, Some(Some((TokenTree::Punct(eq), default)))) => {
+                param.bound = Some(GenericBound {
+                    tk_colon: eq.clone(),
+                    tokens: default.to_vec(),
+                })
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Name and position of the name of the (not yet parsed) declaration
+fn decl_name(input: &[TokenTree]) -> Option<(String, usize)> {
+    let kw = input
+        .iter()
+        .position(|t| matches!(t, TokenTree::Ident(kw) if is_decl_kw(kw)))?;
+    match input.get(kw + 1) {
+        Some(TokenTree::Ident(name)) => Some((name.to_string(), kw + 1)),
+        _ => None,
+    }
+}
+
+/// Bounds in where clauses (`where T: Into<struct Foo {}>`) may contain declarations.
+/// Like generic parameter defaults, these are handled before parsing.
+fn recurse_through_where_clause(
+    input: Vec<TokenTree>,
+    strike_attrs: &[StrikeAttr],
+    ret: &mut TokenStream,
+) -> Vec<TokenTree> {
+    let (name, pos) = match decl_name(&input) {
+        Some(name) => name,
+        None => return input,
+    };
+    let start = match input[pos..]
+        .iter()
+        .position(|t| matches!(t, TokenTree::Ident(kw) if kw == "where"))
+    {
+        Some(start) => pos + start + 1,
+        None => return input,
+    };
+    // The where clause ends at the body or the ; of a tuple struct
+    let mut depth = 0;
+    let clause_end = input[start..].iter().position(|t| match t {
+        TokenTree::Punct(p) if p.as_char() == '<' => {
+            depth += 1;
+            false
+        }
+        TokenTree::Punct(p) if p.as_char() == '>' => {
+            depth -= 1;
+            false
+        }
+        TokenTree::Punct(p) => depth == 0 && p.as_char() == ';',
+        TokenTree::Group(g) => depth == 0 && g.delimiter() == Delimiter::Brace,
+        _ => false,
+    });
+    let clause_end = start + clause_end.unwrap_or(input.len() - start);
+    if !input[start..clause_end]
+        .iter()
+        .any(|t| matches!(t, TokenTree::Ident(kw) if is_decl_kw(kw)))
+    {
+        return input;
+    }
+    let predicates = type_tree(&input[start..clause_end], ret);
+    let path = NameHints {
+        long: true,
+        parent_name: &name,
+        ..Default::default()
+    };
+    let mut out = input[..start].to_vec();
+    let mut tok = &predicates[..];
+    loop {
+        let end = tok.iter().position(|t| get_tt_punct(t, ',').is_some());
+        let current = &tok[..end.unwrap_or(tok.len())];
+        // A single :, not the second half of a ::
+        let colon = (0..current.len()).position(|i| {
+            matches!(get_tt_punct(&current[i], ':'), Some(p) if p.spacing() == Spacing::Alone)
+                && !matches!(
+                    i.checked_sub(1).map(|i| get_tt_punct(&current[i], ':')),
+                    Some(Some(_))
+                )
+        });
+        match colon {
+            Some(colon) => {
+                un_tree_type(&current[..=colon], &mut out);
+                let bounded = current[..colon].iter().rev().find_map(|t| match t {
+                    TypeTree::Token(TokenTree::Ident(i)) => Some(i),
+                    _ => None,
+                });
+                let bounded_name = bounded.map(|p| p.to_string()).unwrap_or_default();
+                let span = bounded.map_or_else(Span::call_site, |p| p.span());
+                let path = path.with_field_name(&bounded_name);
+                recurse_through_type(
+                    &current[colon + 1..],
+                    strike_attrs,
+                    ret,
+                    &Some(path.get_name_hint(None, span)),
+                    false,
+                    &mut out,
+                    &path,
+                );
+            }
+            None => un_tree_type(current, &mut out),
+        }
+        match end {
+            Some(comma) => {
+                un_tree_type(&tok[comma..=comma], &mut out);
+                tok = &tok[comma + 1..];
+            }
+            None => break,
+        }
+    }
+    out.extend_from_slice(&input[clause_end..]);
+    out
+}
+
+fn hack_append_type_decl_semicolon(input_vec: Vec<TokenTree>) -> TokenStream {
+    let is_type_decl = input_vec
+        .iter()
+        .any(|t| matches!(t, TokenTree::Ident(kw) if kw == "type"))
+        && input_vec.iter().all(|t| {
+            matches!(t, TokenTree::Ident(kw) if kw == "type")
+                || !matches!(t, TokenTree::Ident(kw) if is_decl_kw(kw))
+        });
+    let input = match is_type_decl {
+        true => input_vec
+            .into_iter()
+            .chain(once(TokenTree::Punct(Punct::new(';', Spacing::Alone))))
+            .collect(),
+        false => input_vec.into_iter().collect(),
+    };
+    input
+}
+
+/// Removes `#[structstruck::vis(…)]` and inherited `#[structstruck::default_vis(…)]`,
+/// returns the visibility they ask for (`Some(None)` for private), the last one wins
+fn take_vis_attr(
+    attributes: &mut Vec<Attribute>,
+    ret: &mut TokenStream,
+) -> Option<Option<venial::VisMarker>> {
+    let mut vis = None;
+    attributes.retain(|attr| {
+        let attr_name = match ["vis", "default_vis"]
+            .into_iter()
+            .find(|name| check_crate_attr(attr, name))
+        {
+            Some(attr_name) => attr_name,
+            None => return true,
+        };
+        match parse_vis_marker(attr.get_value_tokens()) {
+            Some(marker) => vis = Some(marker),
+            None => report_attr_error(
+                attr,
+                ret,
+                &format!(
+                    "#[structstruck::{}(…)]: expected a visibility, e.g. pub(crate)",
+                    attr_name
+                ),
+            ),
+        }
+        false
+    });
+    vis
+}
+
+/// Whether `#[structstruck::emit(after)]` asks for the nested declarations to go after this one.
+/// `#[structstruck::emit(before)]` is the default.
+fn take_emit_after_attr(attributes: &mut Vec<Attribute>, ret: &mut TokenStream) -> bool {
+    let mut after = false;
+    attributes.retain(|attr| {
+        if !check_crate_attr(attr, "emit") {
+            return true;
+        }
+        match attr.get_value_tokens() {
+            [TokenTree::Ident(pos)] if pos == "before" => after = false,
+            [TokenTree::Ident(pos)] if pos == "after" => after = true,
+            _ => report_attr_error(
+                attr,
+                ret,
+                "#[structstruck::emit(…)]: expected before or after",
+            ),
+        }
+        false
+    });
+    after
+}
+
+/// The path from `#[structstruck::reexport(types)]` or `#[structstruck::reexport(crate::types)]`
+fn take_reexport_attr(
+    attributes: &mut Vec<Attribute>,
+    ret: &mut TokenStream,
+) -> Option<Vec<TokenTree>> {
+    let mut module = None;
+    attributes.retain(|attr| {
+        if !check_crate_attr(attr, "reexport") {
+            return true;
+        }
+        let path = attr.get_value_tokens();
+        // name, or name::name::…
+        let valid = path.len() % 3 == 1
+            && path.chunks(3).all(|segment| match segment {
+                [TokenTree::Ident(_)] => true,
+                [TokenTree::Ident(_), TokenTree::Punct(c1), TokenTree::Punct(c2)] => {
+                    c1.as_char() == ':' && c1.spacing() == Spacing::Joint && c2.as_char() == ':'
+                }
+                _ => false,
+            });
+        match valid {
+            true => module = Some(path.to_vec()),
+            false => report_error(
+                stream_span(path.iter()).or(Some(attr.tk_hash.span())),
+                ret,
+                "#[structstruck::reexport(…)]: expected a module name or path, e.g. reexport(types) or reexport(crate::types)",
+            ),
+        }
+        false
+    });
+    module
+}
+
+fn take_alias_attr(attributes: &mut Vec<Attribute>, ret: &mut TokenStream) -> Option<Ident> {
+    let mut alias = None;
+    attributes.retain(|attr| {
+        if !check_crate_attr(attr, "alias_as") {
+            return true;
+        }
+        match attr.get_value_tokens() {
+            [TokenTree::Ident(name)] => alias = Some(name.clone()),
+            _ => report_attr_error(attr, ret,
+                "#[structstruck::alias_as(…)]: expected a name (associated types like Trait::Item have to be set in the impl)",
+            ),
+        }
+        false
+    });
+    alias
+}
+
+/// `type Alias<T> = Generated<T>;` for `#[structstruck::alias_as(Alias)]`, with the declaration's visibility
+fn alias_decl(alias: &Ident, decl: &Declaration, ret: &mut TokenStream) -> TokenStream {
+    // Type aliases don't have generics, see the TyDefinition arm of strike_definition
+    let no_generics = None;
+    let (vis, name, generics) = match decl {
+        Declaration::Struct(s) => (&s.vis_marker, &s.name, &s.generic_params),
+        Declaration::Enum(e) => (&e.vis_marker, &e.name, &e.generic_params),
+        Declaration::Union(u) => (&u.vis_marker, &u.name, &u.generic_params),
+        Declaration::TyDefinition(t) => (&t.vis_marker, &t.name, &no_generics),
+        _ => {
+            report_error(
+                Some(alias.span()),
+                ret,
+                "#[structstruck::alias_as(…)]: only types can be aliased",
+            );
+            return TokenStream::new();
+        }
+    };
+    let args = generics.as_ref().map(generic_args);
+    // Bounds aren't enforced on type aliases, only const parameters keep their type
+    let params = generics.as_ref().map(|generics| {
+        let mut params = generics.clone();
+        params.params.iter_mut().for_each(|(param, _)| {
+            if !matches!(param.tk_prefix, Some(TokenTree::Ident(_))) {
+                param.bound = None;
+            }
+        });
+        params
+    });
+    quote!(#vis type #alias #params = #name #args;)
+}
+
+fn take_max_types_attr(attributes: &mut Vec<Attribute>, ret: &mut TokenStream) -> Option<usize> {
+    let mut max = None;
+    attributes.retain(|attr| {
+        if !check_crate_attr(attr, "max_types") {
+            return true;
+        }
+        match attr.get_value_tokens() {
+            [TokenTree::Literal(n)] if n.to_string().parse::<usize>().is_ok() => {
+                max = n.to_string().parse().ok()
+            }
+            _ => report_attr_error(
+                attr,
+                ret,
+                "#[structstruck::max_types(…)]: expected a number",
+            ),
+        }
+        false
+    });
+    max
+}
+
+/// The declarations in `generated`, with the position of their keyword
+fn generated_types(generated: &[TokenTree]) -> impl Iterator<Item = (usize, &Ident)> {
+    generated
+        .iter()
+        .zip(generated.iter().skip(1))
+        .enumerate()
+        .filter_map(|(i, t)| match t {
+            (TokenTree::Ident(kw), TokenTree::Ident(name))
+                if kw == "struct" || kw == "enum" || kw == "union" || kw == "type" =>
+            {
+                Some((i, name))
+            }
+            _ => None,
+        })
+}
+
+/// Nested declarations can't have the name of the declaration they are in,
+/// e.g. `enum Foo { Bar { foo: struct {} } }` would generate a second `Foo`
+fn report_name_collisions(name: &Ident, generated: &[TokenTree], ret: &mut TokenStream) {
+    for (_, nested) in generated_types(generated).filter(|(_, nested)| *nested == name) {
+        report_error(
+            Some(nested.span()),
+            ret,
+            &format!(
+                "Nested declaration named {} like the declaration it is in, give it a name of its own, e.g. struct {}Inner {{ … }}",
+                name, name
+            ),
+        );
+    }
+}
+
+/// `pub mod module { pub use super::Type; … }` for all `pub` and `pub(crate)` types in `generated`.
+/// For a path like `crate::outer::types`, the declaration is in `crate::outer`, which is used instead of `super`.
+fn reexport_module(path: &[TokenTree], generated: &[TokenTree]) -> TokenStream {
+    let (module, parent) = match path.split_last() {
+        Some((module, [parent @ .., _, _])) => (module, parent.iter().cloned().collect()),
+        Some((module, _)) => (module, quote!(super)),
+        None => return TokenStream::new(),
+    };
+    let uses = generated_types(generated).filter_map(|(i, name)| {
+        let (vis, mut start) = match &generated[..i] {
+            [.., TokenTree::Ident(pb)] if pb == "pub" => (quote!(pub), i - 1),
+            [.., TokenTree::Ident(pb), TokenTree::Group(g)]
+                if pb == "pub" && g.stream().to_string() == "crate" =>
+            {
+                (quote!(pub(crate)), i - 2)
+            }
+            _ => return None,
+        };
+        // The use must be left out wherever the declaration is
+        let mut cfgs = vec![];
+        while let [.., TokenTree::Punct(hash), TokenTree::Group(attr)] = &generated[..start] {
+            if hash.as_char() != '#' || attr.delimiter() != Delimiter::Bracket {
+                break;
+            }
+            if matches!(attr.stream().into_iter().next(), Some(TokenTree::Ident(cfg)) if cfg == "cfg") {
+                cfgs.insert(0, quote!(#hash #attr));
+            }
+            start -= 2;
+        }
+        Some(quote!(#(#cfgs)* #vis use #parent::#name;))
+    });
+    quote!(pub mod #module { #(#uses)* })
+}
+
+fn parse_vis_marker(tokens: &[TokenTree]) -> Option<Option<venial::VisMarker>> {
+    match tokens {
+        [] => Some(None),
+        [pb @ TokenTree::Ident(kw)] if kw == "pub" => Some(Some(venial::VisMarker {
+            tk_token1: pb.clone(),
+            tk_token2: None,
+        })),
+        [pb @ TokenTree::Ident(kw), restr @ TokenTree::Group(g)]
+            if kw == "pub" && g.delimiter() == Delimiter::Parenthesis =>
+        {
+            Some(Some(venial::VisMarker {
+                tk_token1: pb.clone(),
+                tk_token2: Some(restr.clone()),
+            }))
+        }
+        _ => None,
+    }
+}
+
+pub(crate) fn make_pub_marker() -> venial::VisMarker {
+    venial::VisMarker {
+        tk_token1: TokenTree::Ident(Ident::new("pub", Span::mixed_site())),
+        tk_token2: None,
+    }
+}
+
+pub(crate) fn is_plain_pub(vis_marker: &Option<venial::VisMarker>) -> bool {
+    match vis_marker {
+        Some(venial::VisMarker {
+            tk_token1: TokenTree::Ident(i),
+            tk_token2: None,
+        }) if i.to_string() == "pub" => true,
+        _ => false,
+    }
+}
+
+fn move_out_inner_attrs(input: TokenStream) -> TokenStream {
+    let mut prefix = vec![];
+    let mut ret = vec![];
+    for e in input {
+        match e {
+            TokenTree::Group(g) if g.delimiter() == Delimiter::Brace => {
+                let mut tt: Vec<TokenTree> = vec![];
+                let gt = g.stream().into_iter().collect::<Vec<_>>();
+                let mut gt = &gt[..];
                 loop {
                     match gt {
                         [TokenTree::Punct(hash), TokenTree::Punct(bang), TokenTree::Group(tree), rest @ ..]
@@ -305,7 +2465,7 @@ fn move_out_inner_attrs(input: TokenStream) -> TokenStream {
 
 fn recurse_through_struct_fields(
     fields: &mut venial::StructFields,
-    strike_attrs: &[Attribute],
+    strike_attrs: &[StrikeAttr],
     ret: &mut TokenStream,
     in_pub_enum: bool,
     path: &NameHints,
@@ -322,7 +2482,7 @@ fn recurse_through_struct_fields(
 
 fn named_struct_fields(
     n: &mut venial::NamedStructFields,
-    strike_attrs: &[Attribute],
+    strike_attrs: &[StrikeAttr],
     ret: &mut TokenStream,
     in_pub_enum: bool,
     path: &NameHints,
@@ -332,115 +2492,876 @@ fn named_struct_fields(
         // this is necessary because the path is modified/cleared in the recursion
         let path = path.clone();
         let field_name = field.name.to_string();
-        let field_name = match field_name.starts_with("r#") {
-            true => &field_name[2..],
-            false => &field_name,
+        let doc_name = match path.from_doc {
+            true => doc_name_hint(&field.attributes),
+            false => None,
+        };
+        let private = take_crate_attr(&mut field.attributes, "private");
+        let ttok = mem::take(&mut field.ty.tokens);
+        let path = path.with_field_name(doc_name.as_deref().unwrap_or(&field_name));
+        let name_hint = path.get_name_hint(None, field.name.span());
+        recurse_through_type_list(
+            &type_tree(&ttok, ret),
+            &with_field_attrs(strike_attrs, &field.attributes),
+            ret,
+            &Some(name_hint),
+            !private && (is_plain_pub(&field.vis_marker) || in_pub_enum),
+            &mut field.ty.tokens,
+            &path,
+        );
+    }
+}
+
+/// If a field is only there under some `#[cfg(…)]`, so should be the types declared in it.
+/// Attributes named in `#[structstruck::inherit_field_attrs(…)]` are copied to the types declared directly in it.
+fn with_field_attrs(strike_attrs: &[StrikeAttr], field_attrs: &[Attribute]) -> Vec<StrikeAttr> {
+    let path = |tokens: &[TokenTree]| tokens.iter().cloned().collect::<TokenStream>().to_string();
+    let inherited = strike_attrs
+        .iter()
+        .filter(|struck| check_crate_attr(&struck.attr, "inherit_field_attrs"))
+        .flat_map(|struck| {
+            struck
+                .attr
+                .get_value_tokens()
+                .split(|t| matches!(t, TokenTree::Punct(p) if p.as_char() == ','))
+                .map(path)
+                .collect::<Vec<_>>()
+        })
+        .collect::<HashSet<_>>();
+    let cfgs = field_attrs
+        .iter()
+        .filter(|attr| matches!(&attr.path[..], [TokenTree::Ident(cfg)] if cfg == "cfg"))
+        .cloned()
+        .map(StrikeAttr::new);
+    let copied = field_attrs
+        .iter()
+        .filter(|attr| !is_crate_attr(attr) && inherited.contains(&path(&attr.path)))
+        .map(|attr| StrikeAttr {
+            attr: attr.clone(),
+            depth: Some(0),
+            kinds: vec![],
+        });
+    strike_attrs
+        .iter()
+        .cloned()
+        .chain(cfgs)
+        .chain(copied)
+        .collect()
+}
+
+fn tuple_struct_fields(
+    t: &mut venial::TupleStructFields,
+    strike_attrs: &[StrikeAttr],
+    ret: &mut TokenStream,
+    in_pub_enum: bool,
+    path: &NameHints,
+    span: Span,
+) {
+    // Bare names already generated from earlier fields, for Numbering::OnCollision
+    let mut taken = HashSet::new();
+    for (num, (field, _)) in &mut t.fields.iter_mut().enumerate() {
+        // clone path here to start at the same level for each field
+        // this is necessary because the path is modified/cleared in the recursion
+        let mut path = path.clone();
+        let private = take_crate_attr(&mut field.attributes, "private");
+        let ttok = mem::take(&mut field.ty.tokens);
+        // Tuple fields have no name, so name hints point at the field's type
+        let span = stream_span(ttok.iter()).unwrap_or(span);
+        let ttok = type_tree(&ttok, ret);
+
+        // Slight hack for tuple structs:
+        // struct Foo(pub struct Bar()); is ambigous:
+        // Which does the pub belong to, Bar or Foo::0?
+        // I'd say Bar, but venial parses the pub as the visibility specifier of the current struct field
+        // So, transfer the visibility specifier to the declaration token stream, but only if there isn't already one:
+        // I also don't want to break struct Foo(pub pub struct Bar()); (both Bar and Foo::0 public)
+        let vtok;
+        let ttok = match ttok
+            .iter()
+            .any(|t| matches!(t, TypeTree::Token(TokenTree::Ident(kw)) if kw == "pub"))
+        {
+            true => ttok,
+            false => match mem::take(&mut field.vis_marker) {
+                Some(vis) => {
+                    vtok = vis.into_token_stream().into_iter().collect::<Vec<_>>();
+                    vtok.iter()
+                        .map(TypeTree::Token)
+                        .chain(ttok.into_iter())
+                        .collect()
+                }
+                None => ttok,
+            },
+        };
+        // Same for attributes: In struct Foo(#[derive(Clone)] struct Bar()), they are meant for Bar.
+        // Only those that are for declarations are moved, others like #[serde(…)] stay on the field,
+        // and #[cfg(…)] is copied anyway, see with_field_attrs.
+        let atok;
+        let kw = decl_kws(&ttok).next().map(|(kw, _)| kw);
+        let ttok = match kw {
+            Some(kw) if ttok[..kw].iter().all(is_vis_token) => {
+                let (moved, kept) = mem::take(&mut field.attributes)
+                    .into_iter()
+                    .partition::<Vec<_>, _>(|attr| {
+                        check_crate_attr(attr, "each")
+                            || matches!(&attr.path[..], [TokenTree::Ident(name)]
+                                if name == "derive" || name == "doc" || name == "repr")
+                    });
+                field.attributes = kept;
+                atok = moved
+                    .iter()
+                    .flat_map(|attr| attr.to_token_stream())
+                    .collect::<Vec<_>>();
+                atok.iter().map(TypeTree::Token).chain(ttok).collect()
+            }
+            _ => ttok,
+        };
+        let num = match path.numbering {
+            Numbering::SkipFirst => Some(num).filter(|&n| n > 0),
+            Numbering::Always => Some(num),
+            Numbering::OnCollision => {
+                let bare = path.get_name_hint(None, span).to_string();
+                match uses_name_hint(&ttok) && !taken.insert(bare) {
+                    true => Some(num),
+                    false => None,
+                }
+            }
+            Numbering::Never => None,
+        };
+        let name_hint = path.get_name_hint(num, span);
+        recurse_through_type_list(
+            &ttok,
+            &with_field_attrs(strike_attrs, &field.attributes),
+            ret,
+            &Some(name_hint),
+            !private && (is_plain_pub(&field.vis_marker) || in_pub_enum),
+            &mut field.ty.tokens,
+            &mut path,
+        );
+    }
+}
+
+/// `pub` or the `(crate)` in `pub(crate)`
+fn is_vis_token(t: &TypeTree) -> bool {
+    match t {
+        TypeTree::Token(TokenTree::Ident(pb)) => pb == "pub",
+        TypeTree::Token(TokenTree::Group(g)) => g.delimiter() == Delimiter::Parenthesis,
+        _ => false,
+    }
+}
+
+/// An attribute from `#[structstruck::each[…]]` (or a setting) on its way to the nested declarations
+#[derive(Clone)]
+pub(crate) struct StrikeAttr {
+    attr: Attribute,
+    /// How many more levels of nesting it applies to, from `each(depth = …)`
+    depth: Option<usize>,
+    /// The kinds of declarations it applies to, from e.g. `each(enum)`. All if empty.
+    kinds: Vec<String>,
+}
+impl StrikeAttr {
+    fn new(attr: Attribute) -> Self {
+        StrikeAttr {
+            attr,
+            depth: None,
+            kinds: vec![],
+        }
+    }
+
+    fn applies_to(&self, kind: &str) -> bool {
+        // Functions only pass them on, #[derive(…)] and the like make no sense on them
+        kind != "fn" && (self.kinds.is_empty() || self.kinds.iter().any(|k| k == kind))
+    }
+
+    /// The attribute as it goes on a declaration of `kind`, if at all.
+    /// Type aliases can't derive, so they get neither `derive(…)` nor derive helpers like `serde(…)`,
+    /// also not inside a `cfg_attr(…)`.
+    fn attr_for(&self, kind: &str) -> Option<Attribute> {
+        if !self.applies_to(kind) {
+            return None;
+        }
+        let derives = |attr: &[TokenTree]| matches!(attr.first(), Some(TokenTree::Ident(kw)) if kw == "derive" || kw == "serde");
+        if kind != "type" {
+            return Some(self.attr.clone());
+        }
+        // All the tokens are in the path, see split_crate_attr
+        match &self.attr.path[..] {
+            path if derives(path) => None,
+            [TokenTree::Ident(kw), TokenTree::Group(args)] if kw == "cfg_attr" => {
+                let args = args.stream().into_iter().collect::<Vec<_>>();
+                let mut args =
+                    args.split(|t| matches!(t, TokenTree::Punct(p) if p.as_char() == ','));
+                let predicate = args.next().unwrap_or_default();
+                let attrs = args
+                    .filter(|attr| !attr.is_empty() && !derives(attr))
+                    .collect::<Vec<_>>();
+                if attrs.is_empty() {
+                    return None;
+                }
+                let mut tokens = predicate.iter().cloned().collect::<TokenStream>();
+                for attr in attrs {
+                    tokens.extend(quote! { , });
+                    tokens.extend(attr.iter().cloned());
+                }
+                let mut group = Group::new(Delimiter::Parenthesis, tokens);
+                group.set_span(self.attr.path[1].span());
+                Some(Attribute {
+                    path: vec![self.attr.path[0].clone(), TokenTree::Group(group)],
+                    ..self.attr.clone()
+                })
+            }
+            _ => Some(self.attr.clone()),
+        }
+    }
+}
+
+fn strike_through_attributes(
+    dec_attrs: &mut Vec<Attribute>,
+    strike_attrs: &mut Vec<StrikeAttr>,
+    kind: &str,
+    name: &Ident,
+    ret: &mut TokenStream,
+) {
+    let inherited = strike_attrs.len();
+    let allow_strikethrough = dec_attrs
+        .iter()
+        .chain(strike_attrs.iter().map(|struck| &struck.attr))
+        .any(|attr| check_crate_attr(attr, "allow_strikethrough"));
+    let mut own = Vec::with_capacity(dec_attrs.len());
+    for attr in mem::take(dec_attrs) {
+        let each = check_crate_attr(&attr, "each");
+        let strikethrough =
+            matches!(&attr.path[..], [TokenTree::Ident(kw)] if kw == "strikethrough");
+        if strikethrough && !allow_strikethrough {
+            report_strikethrough_deprecated(ret, attr.path[0].span());
+        }
+        if strikethrough || each {
+            match &attr.value {
+                AttributeValue::Group(delim, value) if delim.delimiter != Delimiter::Brace => {
+                    let mut value = value.to_vec();
+                    // Put there by move_each_filters
+                    let (depth, kinds) = match value.first() {
+                        Some(TokenTree::Group(g)) if g.delimiter() == Delimiter::Parenthesis => {
+                            let filters = g.stream().into_iter().collect::<Vec<_>>();
+                            value.remove(0);
+                            parse_each_filters(&filters, ret)
+                        }
+                        _ => (None, vec![]),
+                    };
+                    while matches!(value.last(), Some(TokenTree::Punct(p)) if p.as_char() == ',') {
+                        value.pop();
+                    }
+                    if !is_attribute_content(&value) {
+                        report_error(
+                            stream_span(value.iter()).or(Some(attr.tk_hash.span())),
+                            ret,
+                            "#[structstruck::each[…]]: … must be an attribute, e.g. each[derive(Debug)]",
+                        );
+                        continue;
+                    }
+                    let (path, value) = split_crate_attr(value);
+                    let struck = StrikeAttr {
+                        attr: Attribute {
+                            tk_bang: attr.tk_bang.clone(),
+                            tk_hash: attr.tk_hash.clone(),
+                            tk_brackets: attr.tk_brackets.clone(),
+                            path,
+                            value,
+                        },
+                        depth,
+                        kinds,
+                    };
+                    // Keep the position the user wrote it at
+                    own.extend(struck.attr_for(kind));
+                    strike_attrs.push(struck);
+                }
+                value => {
+                    let found = match value {
+                        AttributeValue::Group(..) => "{group}",
+                        AttributeValue::Equals(..) => "= value",
+                        AttributeValue::Empty => "nothing",
+                    };
+                    report_attr_error(
+                        &attr,
+                        ret,
+                        &format!(
+                            "#[structstruck::each …]: … must be a [group] or (group), found {}",
+                            found
+                        ),
+                    );
+                }
+            };
+        } else if [
+            "each_field",
+            "name_prefix",
+            "name_suffix",
+            "name_map",
+            "derive_for",
+            "sort_fields",
+            "each_impl",
+            "raw_names",
+            "inherit_field_attrs",
+            "config",
+        ]
+        .iter()
+        .any(|name| check_crate_attr(&attr, name))
+        {
+            // These apply to the whole tree without each
+            strike_attrs.push(StrikeAttr::new(attr.clone()));
+            own.push(attr);
+        } else if check_crate_attr(&attr, "each_if") {
+            // Resolved by resolve_each_if once the fields are known
+            if check_each_if(&attr, ret) {
+                if kind != "fn" {
+                    own.push(attr.clone());
+                }
+                strike_attrs.push(StrikeAttr::new(attr));
+            }
+        } else if check_crate_attr(&attr, "default_vis") {
+            // Only for the nested declarations, where it works like structstruck::vis
+            strike_attrs.push(StrikeAttr::new(attr));
+        } else if check_crate_attr(&attr, "allow_dead_code") {
+            // Only for the nested declarations, see allow_dead_code
+            strike_attrs.push(StrikeAttr::new(attr));
+        } else if check_crate_attr(&attr, "allow_strikethrough") {
+            // Only checked above
+            strike_attrs.push(StrikeAttr::new(attr));
+        } else {
+            own.push(attr);
+        }
+    }
+
+    // Inherited attributes go after the declaration's own, some attribute macros care about order.
+    // Settings for structstruck itself go first though, so more specific settings override them.
+    let (mut settings, inherited): (Vec<_>, Vec<_>) = strike_attrs[..inherited]
+        .iter()
+        .filter_map(|struck| struck.attr_for(kind))
+        .partition(is_crate_attr);
+    settings.retain(|attr| !check_crate_attr(attr, "allow_strikethrough"));
+    if take_crate_attr(&mut own, "no_each_field") {
+        let not_each_field = |attr: &Attribute| !check_crate_attr(attr, "each_field");
+        strike_attrs.retain(|struck| not_each_field(&struck.attr));
+        settings.retain(not_each_field);
+        own.retain(not_each_field);
+    }
+    *dec_attrs = settings.into_iter().chain(own).chain(inherited).collect();
+    derive_for(dec_attrs, kind, name, ret);
+    derive_here(dec_attrs, ret);
+    // One level down for the nested declarations
+    strike_attrs.retain_mut(|struck| match &mut struck.depth {
+        Some(0) => false,
+        Some(depth) => {
+            *depth -= 1;
+            true
+        }
+        None => true,
+    });
+}
+
+/// Splits the tokens of an attribute from `structstruck::each` into path and value.
+/// Only settings for structstruck itself are split properly, so [`check_crate_attr`] finds them.
+/// Hack a bit: For anything else, all the tokens go into the path, none in the value.
+fn split_crate_attr(tokens: Vec<TokenTree>) -> (Vec<TokenTree>, AttributeValue) {
+    use TokenTree::{Group, Ident, Punct};
+    let value = match &tokens[..] {
+        [Ident(crat), Punct(c1), Punct(c2), Ident(_), value @ ..]
+            if crat == env!("CARGO_CRATE_NAME") && c1.as_char() == ':' && c2.as_char() == ':' =>
+        {
+            match value {
+                [] => AttributeValue::Empty,
+                [Group(g)] if g.delimiter() != Delimiter::None => AttributeValue::Group(
+                    venial::GroupSpan::new(g),
+                    g.stream().into_iter().collect(),
+                ),
+                [Punct(eq), value @ ..] if eq.as_char() == '=' => {
+                    AttributeValue::Equals(eq.clone(), value.to_vec())
+                }
+                _ => return (tokens, AttributeValue::Empty),
+            }
+        }
+        _ => return (tokens, AttributeValue::Empty),
+    };
+    (tokens[..4].to_vec(), value)
+}
+
+/// The traits and bodies from `#[structstruck::each_impl(Trait)]` and `#[structstruck::each_impl(Trait { … })]`
+fn take_each_impls(
+    attributes: &mut Vec<Attribute>,
+    ret: &mut TokenStream,
+) -> Vec<(TokenStream, TokenStream)> {
+    let mut impls = vec![];
+    attributes.retain(|attr| {
+        if !check_crate_attr(attr, "each_impl") {
+            return true;
+        }
+        let (tr, body) = match attr.get_value_tokens() {
+            [tr @ .., TokenTree::Group(body)] if body.delimiter() == Delimiter::Brace => {
+                (tr, body.stream())
+            }
+            tr => (tr, TokenStream::new()),
+        };
+        match (&attr.value, tr) {
+            (AttributeValue::Group(..), [_, ..]) => {
+                impls.push((tr.iter().cloned().collect(), body))
+            }
+            _ => report_attr_error(attr, ret,
+                "#[structstruck::each_impl(…)]: expected a trait, e.g. each_impl(Marker) or each_impl(Named { fn name() {} })",
+            ),
+        }
+        false
+    });
+    impls
+}
+
+/// `impl Trait for Name { body }` for each trait from [`take_each_impls`], for structs, enums, and unions
+fn each_impls(decl: &Declaration, impls: &[(TokenStream, TokenStream)]) -> TokenStream {
+    let (name, generics, where_clause) = match decl {
+        Declaration::Struct(s) => (&s.name, &s.generic_params, &s.where_clause),
+        Declaration::Enum(e) => (&e.name, &e.generic_params, &e.where_clause),
+        Declaration::Union(u) => (&u.name, &u.generic_params, &u.where_clause),
+        _ => return TokenStream::new(),
+    };
+    let args = generics.as_ref().map(generic_args);
+    let generics = generics.as_ref().map(impl_params);
+    let impls = impls.iter().map(|(tr, body)| {
+        quote! {
+            impl #generics #tr for #name #args #where_clause {
+                #body
+            }
+        }
+    });
+    quote!(#(#impls)*)
+}
+
+/// Whether the tokens look like the inside of an attribute:
+/// a path, optionally followed by a delimited group or `= value`
+fn is_attribute_content(tokens: &[TokenTree]) -> bool {
+    let mut rest = match tokens {
+        [TokenTree::Punct(c1), TokenTree::Punct(c2), rest @ ..]
+            if c1.as_char() == ':' && c2.as_char() == ':' =>
+        {
+            rest
+        }
+        rest => rest,
+    };
+    loop {
+        rest = match rest {
+            [TokenTree::Ident(_), rest @ ..] => rest,
+            _ => return false,
+        };
+        rest = match rest {
+            [TokenTree::Punct(c1), TokenTree::Punct(c2), rest @ ..]
+                if c1.as_char() == ':' && c2.as_char() == ':' =>
+            {
+                rest
+            }
+            [] | [TokenTree::Group(_)] => return true,
+            [TokenTree::Punct(eq), _, ..] => return eq.as_char() == '=',
+            _ => return false,
+        };
+    }
+}
+
+/// Checks the `(copy) attr` in `#[structstruck::each_if[(copy) attr]]`, put there by [`move_each_filters`]
+fn check_each_if(attr: &Attribute, ret: &mut TokenStream) -> bool {
+    let (cond, payload) = match &attr.value {
+        AttributeValue::Group(delim, value) if delim.delimiter == Delimiter::Bracket => {
+            match &value[..] {
+                [TokenTree::Group(cond), payload @ ..]
+                    if cond.delimiter() == Delimiter::Parenthesis =>
+                {
+                    (cond.stream().into_iter().collect::<Vec<_>>(), payload)
+                }
+                _ => (vec![], &[][..]),
+            }
+        }
+        _ => (vec![], &[][..]),
+    };
+    match &cond[..] {
+        [TokenTree::Ident(copy)] if copy == "copy" && is_attribute_content(payload) => true,
+        [TokenTree::Ident(copy)] if copy == "copy" => {
+            report_error(
+                stream_span(payload.iter()).or(Some(attr.tk_hash.span())),
+                ret,
+                "#[structstruck::each_if(…)[…]]: … must be an attribute, e.g. each_if(copy)[derive(Clone, Copy)]",
+            );
+            false
+        }
+        _ => {
+            report_error(
+                stream_span(cond.iter()).or(Some(attr.tk_hash.span())),
+                ret,
+                "#[structstruck::each_if(…)[…]]: expected each_if(copy)[attribute]",
+            );
+            false
+        }
+    }
+}
+
+/// Replaces an inherited `#[structstruck::allow_dead_code]` by `#[allow(dead_code)]`, once
+fn allow_dead_code(attributes: &mut Vec<Attribute>) {
+    let mut found = false;
+    attributes.retain_mut(|attr| {
+        if !check_crate_attr(attr, "allow_dead_code") {
+            return true;
+        }
+        if mem::replace(&mut found, true) {
+            return false;
+        }
+        let span = attr.path.last().map_or_else(Span::call_site, |t| t.span());
+        let mut lint = Group::new(Delimiter::Parenthesis, quote_spanned!(span=> dead_code));
+        lint.set_span(span);
+        // Like the attributes from structstruck::each, see strike_through_attributes
+        attr.path = vec![
+            TokenTree::Ident(Ident::new("allow", span)),
+            TokenTree::Group(lint),
+        ];
+        attr.value = AttributeValue::Empty;
+        true
+    });
+}
+
+/// Replaces `#[structstruck::each_if[(copy) attr]]` by `#[attr]` if the declaration can be `Copy`,
+/// drops it otherwise
+fn resolve_each_if(attributes: &mut Vec<Attribute>, copy: bool, ret: &mut TokenStream) {
+    attributes.retain_mut(|attr| {
+        if !check_crate_attr(attr, "each_if") {
+            return true;
+        }
+        let (delim, value) = match (&attr.value, copy) {
+            (AttributeValue::Group(delim, value), true) => (delim.clone(), value[1..].to_vec()),
+            _ => return false,
+        };
+        match &value[..] {
+            // Through derive_here, so traits that are derived already aren't derived twice
+            [TokenTree::Ident(derive), TokenTree::Group(traits)] if derive == "derive" => {
+                attr.path[3] = TokenTree::Ident(Ident::new("derive_here", attr.path[3].span()));
+                attr.value = AttributeValue::Group(delim, traits.stream().into_iter().collect());
+            }
+            _ => {
+                // Same hack as for structstruck::each, all tokens go into the path
+                attr.path = value;
+                attr.value = AttributeValue::Empty;
+            }
+        }
+        true
+    });
+    derive_here(attributes, ret);
+}
+
+/// Whether all fields of a struct, enum, or union are known to be `Copy`.
+/// Deliberately conservative, anything it doesn't know about (e.g. generic parameters) is not.
+fn fields_copy(decl: &Declaration, copy_types: &HashSet<String>) -> bool {
+    let copy = |types: Vec<&Vec<TokenTree>>| types.iter().all(|ty| is_copy_type(ty, copy_types));
+    match decl {
+        Declaration::Struct(s) => copy(field_types(&s.fields)),
+        Declaration::Enum(e) => e
+            .variants
+            .iter()
+            .all(|(v, _)| copy(field_types(&v.contents))),
+        Declaration::Union(u) => copy(u.fields.fields.iter().map(|(f, _)| &f.ty.tokens).collect()),
+        _ => false,
+    }
+}
+
+fn is_copy_type(ty: &[TokenTree], copy_types: &HashSet<String>) -> bool {
+    const PRIMITIVES: &[&str] = &[
+        "u8", "u16", "u32", "u64", "u128", "usize", "i8", "i16", "i32", "i64", "i128", "isize",
+        "f32", "f64", "bool", "char",
+    ];
+    let split = |tokens: Vec<TokenTree>, c: char| {
+        tokens
+            .split(|t| matches!(t, TokenTree::Punct(p) if p.as_char() == c))
+            .map(|part| part.to_vec())
+            .collect::<Vec<_>>()
+    };
+    match ty {
+        [TokenTree::Ident(name)] => {
+            PRIMITIVES.contains(&name.to_string().as_str())
+                || copy_types.contains(&name.to_string())
+        }
+        // Shared references, but not &mut or &'a mut
+        [TokenTree::Punct(amp), rest @ ..] if amp.as_char() == '&' => {
+            let rest = match rest {
+                [TokenTree::Punct(tick), TokenTree::Ident(_), rest @ ..]
+                    if tick.as_char() == '\'' =>
+                {
+                    rest
+                }
+                rest => rest,
+            };
+            rest.first()
+                .is_some_and(|t| !matches!(t, TokenTree::Ident(kw) if kw == "mut"))
+        }
+        // Tuples, () included
+        [TokenTree::Group(g)] if g.delimiter() == Delimiter::Parenthesis => {
+            split(g.stream().into_iter().collect(), ',')
+                .iter()
+                .filter(|ty| !ty.is_empty())
+                .all(|ty| is_copy_type(ty, copy_types))
+        }
+        // Arrays, not slices
+        [TokenTree::Group(g)] if g.delimiter() == Delimiter::Bracket => {
+            match &split(g.stream().into_iter().collect(), ';')[..] {
+                [elem, _] => is_copy_type(elem, copy_types),
+                _ => false,
+            }
+        }
+        // Added by add_phantom_field
+        [.., TokenTree::Ident(phantom), TokenTree::Punct(open), _, TokenTree::Punct(close)]
+            if phantom == "PhantomData" && open.as_char() == '<' && close.as_char() == '>' =>
+        {
+            true
+        }
+        _ => false,
+    }
+}
+
+/// The names of the types in `generated` that derive `Copy`
+fn copy_types(generated: &[TokenTree]) -> HashSet<String> {
+    let mut types = HashSet::new();
+    let mut copy = false;
+    for (i, t) in generated.iter().enumerate() {
+        match (t, generated.get(i + 1)) {
+            (TokenTree::Group(attr), _) if attr.delimiter() == Delimiter::Bracket => {
+                let tokens = attr.stream().into_iter().collect::<Vec<_>>();
+                copy |= matches!(&tokens[..], [TokenTree::Ident(derive), TokenTree::Group(traits)]
+                    if derive == "derive"
+                        && traits.stream().into_iter().any(|t| matches!(t, TokenTree::Ident(c) if c == "Copy")));
+            }
+            (TokenTree::Ident(kw), Some(TokenTree::Ident(name)))
+                if kw == "struct" || kw == "enum" || kw == "union" =>
+            {
+                if copy {
+                    types.insert(name.to_string());
+                }
+                copy = false;
+            }
+            (TokenTree::Ident(kw), _) if kw == "type" || kw == "fn" || kw == "impl" => copy = false,
+            _ => (),
+        }
+    }
+    types
+}
+
+/// Turns `#[structstruck::derive_for(Name => …)]` into `#[structstruck::derive_here(…)]`
+/// on the declaration called `Name`, and drops it everywhere else
+fn derive_for(attributes: &mut Vec<Attribute>, kind: &str, name: &Ident, ret: &mut TokenStream) {
+    attributes.retain_mut(|attr| {
+        if !check_crate_attr(attr, "derive_for") {
+            return true;
+        }
+        let (target, traits) = match (&attr.value, attr.get_value_tokens()) {
+            (
+                AttributeValue::Group(..),
+                [TokenTree::Ident(target), TokenTree::Punct(eq), TokenTree::Punct(gt), traits @ ..],
+            ) if eq.as_char() == '=' && eq.spacing() == Spacing::Joint && gt.as_char() == '>' => {
+                (target.clone(), traits.to_vec())
+            }
+            _ => {
+                report_attr_error(attr, ret,
+                    "#[structstruck::derive_for(…)]: expected e.g. derive_for(Name => Clone, Debug)",
+                );
+                return false;
+            }
+        };
+        if target != *name || !["struct", "enum", "union"].contains(&kind) {
+            return false;
+        }
+        if let AttributeValue::Group(_, value) = &mut attr.value {
+            *value = traits;
+        }
+        attr.path[3] = TokenTree::Ident(Ident::new("derive_here", attr.path[3].span()));
+        true
+    });
+}
+
+/// Turns `#[structstruck::derive_here(…)]` into a `#[derive(…)]` of the traits that aren't derived yet
+fn derive_here(attributes: &mut Vec<Attribute>, ret: &mut TokenStream) {
+    let mut derived = attributes
+        .iter()
+        .flat_map(derived_traits)
+        .collect::<HashSet<_>>();
+    attributes.retain_mut(|attr| {
+        if !check_crate_attr(attr, "derive_here") {
+            return true;
+        }
+        let (delim, traits) = match &attr.value {
+            AttributeValue::Group(delim, traits) => (delim.clone(), traits),
+            _ => {
+                report_error(
+                    Some(attr.tk_hash.span()),
+                    ret,
+                    "#[structstruck::derive_here(…)]: expected a list of traits",
+                );
+                return false;
+            }
+        };
+        let mut list = vec![];
+        for tr in traits.split(|t| matches!(t, TokenTree::Punct(p) if p.as_char() == ',')) {
+            let name = tr.iter().cloned().collect::<TokenStream>().to_string();
+            if !tr.is_empty() && derived.insert(name) {
+                if !list.is_empty() {
+                    list.push(TokenTree::Punct(Punct::new(',', Spacing::Alone)));
+                }
+                list.extend(tr.iter().cloned());
+            }
+        }
+        if list.is_empty() {
+            return false;
+        }
+        let span = attr.path.last().map_or_else(Span::call_site, |t| t.span());
+        attr.path = vec![TokenTree::Ident(Ident::new("derive", span))];
+        // The value may have come in […], e.g. from structstruck::each_if
+        let delim = venial::GroupSpan {
+            delimiter: Delimiter::Parenthesis,
+            span: delim.span,
         };
-        let ttok = mem::take(&mut field.ty.tokens);
-        let path = path.with_field_name(field_name);
-        let name_hint = path.get_name_hint(None, field.name.span());
-        recurse_through_type_list(
-            &type_tree(&ttok, ret),
-            strike_attrs,
-            ret,
-            &Some(name_hint),
-            is_plain_pub(&field.vis_marker) || in_pub_enum,
-            &mut field.ty.tokens,
-            &path,
-        );
+        attr.value = AttributeValue::Group(delim, list);
+        true
+    });
+}
+
+/// Internally tagged enums (`#[serde(tag = "…")]` without `content`) can't have tuple variants,
+/// report them here instead of leaving it to serde's error on the generated code
+fn check_internally_tagged(e: &venial::Enum, ret: &mut TokenStream) {
+    let keys = e.attributes.iter().flat_map(serde_keys).collect::<Vec<_>>();
+    let tagged =
+        keys.iter().any(|k| k == "tag") && !keys.iter().any(|k| k == "content" || k == "untagged");
+    if !tagged {
+        return;
+    }
+    for (v, _) in e.variants.iter() {
+        if let StructFields::Tuple(t) = &v.contents {
+            // Newtype variants are fine as long as their content is a struct or map
+            if t.fields.len() != 1 {
+                report_error(
+                    Some(v.name.span()),
+                    ret,
+                    &format!(
+                        "Variant {} of internally tagged enum {} must not be a tuple, use named fields, e.g. {} {{ … }}",
+                        v.name, e.name, v.name
+                    ),
+                );
+            }
+        }
     }
 }
 
-fn tuple_struct_fields(
-    t: &mut venial::TupleStructFields,
-    strike_attrs: &[Attribute],
-    ret: &mut TokenStream,
-    in_pub_enum: bool,
-    path: &NameHints,
-    span: Span,
-) {
-    for (num, (field, _)) in &mut t.fields.iter_mut().enumerate() {
-        // clone path here to start at the same level for each field
-        // this is necessary because the path is modified/cleared in the recursion
-        let mut path = path.clone();
-        let ttok = mem::take(&mut field.ty.tokens);
-        let ttok = type_tree(&ttok, ret);
+/// The keys in `#[serde(tag = "type", rename_all = "…")]`, also in the `structstruck::each` form
+fn serde_keys(attr: &Attribute) -> Vec<String> {
+    let list = match (&attr.path[..], &attr.value) {
+        ([TokenTree::Ident(serde)], AttributeValue::Group(_, list)) if serde == "serde" => {
+            list.clone()
+        }
+        ([TokenTree::Ident(serde), TokenTree::Group(g)], AttributeValue::Empty)
+            if serde == "serde" =>
+        {
+            g.stream().into_iter().collect()
+        }
+        _ => return vec![],
+    };
+    list.split(|t| matches!(t, TokenTree::Punct(p) if p.as_char() == ','))
+        .filter_map(|kv| match kv.first() {
+            Some(TokenTree::Ident(key)) => Some(key.to_string()),
+            _ => None,
+        })
+        .collect()
+}
 
-        // Slight hack for tuple structs:
-        // struct Foo(pub struct Bar()); is ambigous:
-        // Which does the pub belong to, Bar or Foo::0?
-        // I'd say Bar, but venial parses the pub as the visibility specifier of the current struct field
-        // So, transfer the visibility specifier to the declaration token stream, but only if there isn't already one:
-        // I also don't want to break struct Foo(pub pub struct Bar()); (both Bar and Foo::0 public)
-        let vtok;
-        let ttok = match ttok
-            .iter()
-            .any(|t| matches!(t, TypeTree::Token(TokenTree::Ident(kw)) if kw == "pub"))
+/// The traits in a `#[derive(…)]`, also if it came from `#[structstruck::each[derive(…)]]`
+fn derived_traits(attr: &Attribute) -> Vec<String> {
+    let list = match (&attr.path[..], &attr.value) {
+        ([TokenTree::Ident(derive)], AttributeValue::Group(_, list)) if derive == "derive" => {
+            list.clone()
+        }
+        ([TokenTree::Ident(derive), TokenTree::Group(g)], AttributeValue::Empty)
+            if derive == "derive" =>
         {
-            true => ttok,
-            false => match mem::take(&mut field.vis_marker) {
-                Some(vis) => {
-                    vtok = vis.into_token_stream().into_iter().collect::<Vec<_>>();
-                    vtok.iter()
-                        .map(TypeTree::Token)
-                        .chain(ttok.into_iter())
-                        .collect()
-                }
-                None => ttok,
-            },
-        };
-        let name_hint = path.get_name_hint(Some(num), span);
-        recurse_through_type_list(
-            &ttok,
-            strike_attrs,
-            ret,
-            &Some(name_hint),
-            is_plain_pub(&field.vis_marker) || in_pub_enum,
-            &mut field.ty.tokens,
-            &mut path,
-        );
-    }
+            g.stream().into_iter().collect()
+        }
+        _ => return vec![],
+    };
+    list.split(|t| matches!(t, TokenTree::Punct(p) if p.as_char() == ','))
+        .filter(|tr| !tr.is_empty())
+        .map(|tr| tr.iter().cloned().collect::<TokenStream>().to_string())
+        .collect()
 }
 
-fn strike_through_attributes(
-    dec_attrs: &mut Vec<Attribute>,
-    strike_attrs: &mut Vec<Attribute>,
+/// Parses the `depth = 1, enum` in `#[structstruck::each(depth = 1, enum)[…]]`
+fn parse_each_filters(
+    filters: &[TokenTree],
     ret: &mut TokenStream,
-) {
-    dec_attrs.retain(|attr| {
-        let each = check_crate_attr(attr, "each");
-        let strikethrough =
-            matches!(&attr.path[..], [TokenTree::Ident(kw)] if kw == "strikethrough");
-        if strikethrough {
-            report_strikethrough_deprecated(ret, attr.path[0].span());
-        }
-        if strikethrough || each {
-            match &attr.value {
-                AttributeValue::Group(brackets, value) => {
-                    strike_attrs.push(Attribute {
-                        tk_bang: attr.tk_bang.clone(),
-                        tk_hash: attr.tk_hash.clone(),
-                        tk_brackets: brackets.clone(),
-                        // Hack a bit: Put all the tokens into the path, none in the value.
-                        path: value.to_vec(),
-                        value: AttributeValue::Empty,
-                    });
-                }
-                _ => {
-                    report_error(
-                        stream_span(attr.get_value_tokens().iter()),
-                        ret,
-                        "#[structstruck::each …]: … must be a [group]",
-                    );
-                }
-            };
-            false
-        } else {
-            true
+) -> (Option<usize>, Vec<String>) {
+    let mut depth = None;
+    let mut kinds = vec![];
+    let filters = filters.split(|t| matches!(t, TokenTree::Punct(p) if p.as_char() == ','));
+    for filter in filters {
+        match filter {
+            [] => (),
+            [TokenTree::Ident(d), TokenTree::Punct(eq), TokenTree::Literal(n)]
+                if d == "depth"
+                    && eq.as_char() == '='
+                    && n.to_string().parse::<usize>().is_ok() =>
+            {
+                depth = n.to_string().parse().ok()
+            }
+            [TokenTree::Ident(kind)]
+                if kind == "struct" || kind == "enum" || kind == "union" || kind == "type" =>
+            {
+                kinds.push(kind.to_string())
+            }
+            _ => report_error(
+                stream_span(filter.iter()),
+                ret,
+                "#[structstruck::each(…)[…]]: expected depth = N, struct, enum, union, or type",
+            ),
         }
-    });
+    }
+    (depth, kinds)
+}
+
+/// Venial can't make sense of `#[structstruck::each(filters)[attr]]`,
+/// it's turned into `#[structstruck::each[(filters) attr]]` for [`strike_through_attributes`].
+/// Same for the condition of `#[structstruck::each_if(copy)[attr]]`.
+fn move_each_filters(input: TokenStream) -> TokenStream {
+    input
+        .into_iter()
+        .map(|t| match t {
+            TokenTree::Group(attr) if attr.delimiter() == Delimiter::Bracket => {
+                let mut tokens = attr.stream().into_iter().collect::<Vec<_>>();
+                let (filters, value) = match &tokens[..] {
+                    [TokenTree::Ident(crat), TokenTree::Punct(c1), TokenTree::Punct(c2), TokenTree::Ident(each), TokenTree::Group(filters), TokenTree::Group(value)]
+                        if crat == env!("CARGO_CRATE_NAME")
+                            && c1.as_char() == ':'
+                            && c2.as_char() == ':'
+                            && (each == "each" || each == "each_if")
+                            && filters.delimiter() == Delimiter::Parenthesis
+                            && value.delimiter() == Delimiter::Bracket =>
+                    {
+                        (filters.clone(), value.clone())
+                    }
+                    _ => return TokenTree::Group(attr),
+                };
+                let mut merged = Group::new(
+                    Delimiter::Bracket,
+                    once(TokenTree::Group(filters))
+                        .chain(value.stream())
+                        .collect(),
+                );
+                merged.set_span(value.span());
+                tokens.truncate(4);
+                tokens.push(TokenTree::Group(merged));
+                let mut attr_group = Group::new(Delimiter::Bracket, tokens.into_iter().collect());
+                attr_group.set_span(attr.span());
+                TokenTree::Group(attr_group)
+            }
+            t => t,
+        })
+        .collect()
+}
 
-    dec_attrs.splice(0..0, strike_attrs.iter().cloned());
+fn is_crate_attr(attr: &Attribute) -> bool {
+    matches!(attr.path.first(), Some(TokenTree::Ident(crat)) if crat == env!("CARGO_CRATE_NAME"))
 }
 
 fn report_strikethrough_deprecated(ret: &mut TokenStream, span: Span) {
@@ -459,6 +3380,20 @@ fn report_strikethrough_deprecated(ret: &mut TokenStream, span: Span) {
     q.to_tokens(ret);
 }
 
+fn report_debug_output(ret: &mut TokenStream, span: Span, generated: &TokenStream) {
+    // Same trick as above, but wrapped in an anonymous const so it can be used more than once
+    let note = format!("structstruck generated: {}", generated);
+    let q = quote_spanned!(span =>
+        const _: () = {
+            #[deprecated(note = #note)]
+            #[allow(non_upper_case_globals)]
+            const structstruck_debug: () = ();
+            let _ = structstruck_debug;
+        };
+    );
+    q.to_tokens(ret);
+}
+
 fn get_tt_punct<'t>(t: &'t TypeTree<'t>, c: char) -> Option<&'t Punct> {
     match t {
         TypeTree::Token(TokenTree::Punct(p)) if p.as_char() == c => Some(p),
@@ -466,24 +3401,74 @@ fn get_tt_punct<'t>(t: &'t TypeTree<'t>, c: char) -> Option<&'t Punct> {
     }
 }
 
+/// Appends to a name hint, which may be a raw identifier like `r#type` with raw_names
+fn extend_name_hint(hint: &Ident, tail: &str) -> Ident {
+    let name = hint.to_string();
+    let name = name.strip_prefix("r#").unwrap_or(&name);
+    Ident::new(&format!("{}{}", name, tail), hint.span())
+}
+
+/// Numbers a name hint like [`NameHints::get_name_hint`] does, before the name suffix
+fn numbered_name_hint(hint: &Ident, path: &NameHints, num: usize) -> Ident {
+    let name = hint.to_string();
+    let name = name.strip_prefix("r#").unwrap_or(&name);
+    let (name, suffix) = match name.strip_suffix(path.suffix) {
+        Some(name) if !name.is_empty() => (name, path.suffix),
+        _ => (name, ""),
+    };
+    let name = format!("{}{}{}{}", name, path.number_separator, num, suffix);
+    Ident::new(&name, hint.span())
+}
+
 fn recurse_through_type_list(
     tok: &[TypeTree],
-    strike_attrs: &[Attribute],
+    strike_attrs: &[StrikeAttr],
     ret: &mut TokenStream,
     name_hint: &Option<Ident>,
     pub_hint: bool,
     type_ret: &mut Vec<TokenTree>,
     path: &NameHints,
 ) {
+    // If several anonymous declarations share one name hint, e.g. in Result<struct {…}, struct {…}>,
+    // all but the first get their position appended
+    let anonymous = tok
+        .split(|t| get_tt_punct(t, ',').is_some())
+        .filter(|t| uses_name_hint(t))
+        .count();
+    // With #[structstruck::map_names], the two arguments of a …Map get Key and Value instead
+    let map = path.map_names
+        && path.wrapper.is_some_and(|w| w.to_string().ends_with("Map"))
+        && tok
+            .split(|t| get_tt_punct(t, ',').is_some())
+            .filter(|t| !t.is_empty())
+            .count()
+            == 2;
+    let mut anonymous_seen = 0;
     let mut tok = tok;
-    loop {
+    for pos in 0.. {
         let end = tok.iter().position(|t| get_tt_punct(t, ',').is_some());
         let current = &tok[..end.unwrap_or(tok.len())];
+        let positional_hint = match name_hint {
+            Some(hint) if map && pos < 2 && uses_name_hint(current) => {
+                Some(extend_name_hint(hint, ["Key", "Value"][pos]))
+            }
+            Some(hint) if anonymous > 1 && uses_name_hint(current) => {
+                anonymous_seen += 1;
+                match anonymous_seen > 1 {
+                    true => Some(numbered_name_hint(hint, path, pos)),
+                    false => None,
+                }
+            }
+            _ => None,
+        };
         recurse_through_type(
             current,
             strike_attrs,
             ret,
-            name_hint,
+            match positional_hint {
+                Some(_) => &positional_hint,
+                None => name_hint,
+            },
             pub_hint,
             type_ret,
             path,
@@ -501,52 +3486,156 @@ fn recurse_through_type_list(
 }
 fn recurse_through_type(
     tok: &[TypeTree],
-    strike_attrs: &[Attribute],
+    strike_attrs: &[StrikeAttr],
     ret: &mut TokenStream,
     name_hint: &Option<Ident>,
     pub_hint: bool,
     type_ret: &mut Vec<TokenTree>,
     path: &NameHints,
 ) {
-    if let Some(c) = tok.windows(3).find_map(|t| {
+    if tok.windows(3).any(|t| {
         get_tt_punct(&t[0], ':')
             .or(get_tt_punct(&t[2], ':'))
             .is_none()
-            .then(|| get_tt_punct(&t[1], ':'))
-            .flatten()
+            && get_tt_punct(&t[1], ':').is_some()
     }) {
-        report_error(
-            Some(c.span()),
+        let mut tokens = vec![];
+        un_tree_type(tok, &mut tokens);
+        report_error_spanning(
+            &tokens,
             ret,
             "Colon in top level of type expression. Did you forget a comma somewhere?",
         );
     }
-    let kw = tok.iter().position(|t| get_decl_ident(t).is_some());
+    // Associated type bindings, e.g. the Item = … in impl Iterator<Item = struct {…}>
+    if let [TypeTree::Token(TokenTree::Ident(binding)), TypeTree::Token(TokenTree::Punct(eq)), rest @ ..] =
+        tok
+    {
+        if eq.as_char() == '=' && decl_kws(rest).next().is_some() {
+            type_ret.push(TokenTree::Ident(binding.clone()));
+            type_ret.push(TokenTree::Punct(eq.clone()));
+            let binding_name = binding.to_string();
+            let path = path.with_field_name(&binding_name);
+            let name_hint = Some(path.get_name_hint(None, binding.span()));
+            recurse_through_type(
+                rest,
+                strike_attrs,
+                ret,
+                &name_hint,
+                pub_hint,
+                type_ret,
+                &path,
+            );
+            return;
+        }
+    }
+    // Return types of Fn(…) -> T or fn(…) -> T, unless the -> belongs to a declaration's where clause
+    let arrow = tok.windows(2).position(|t| {
+        matches!(get_tt_punct(&t[0], '-'), Some(dash) if dash.spacing() == Spacing::Joint)
+            && get_tt_punct(&t[1], '>').is_some()
+    });
+    let kw = decl_kws(tok).next().map(|(kw, _)| kw);
+    if let Some(arrow) = arrow.filter(|&arrow| kw.is_none_or(|kw| arrow < kw)) {
+        recurse_through_type(
+            &tok[..arrow],
+            strike_attrs,
+            ret,
+            name_hint,
+            pub_hint,
+            type_ret,
+            path,
+        );
+        un_tree_type(&tok[arrow..arrow + 2], type_ret);
+        let output_hint = name_hint
+            .as_ref()
+            .map(|hint| extend_name_hint(hint, "Output"));
+        recurse_through_type(
+            &tok[arrow + 2..],
+            strike_attrs,
+            ret,
+            &output_hint,
+            pub_hint,
+            type_ret,
+            path,
+        );
+        return;
+    }
     if let Some(kw) = kw {
-        if let Some(dup) = tok[kw + 1..].iter().find_map(get_decl_ident) {
-            report_error(
-                Some(dup.span()),
+        if let Some((dup, _)) = decl_kws(tok).nth(1) {
+            let mut tokens = vec![];
+            un_tree_type(&tok[dup..], &mut tokens);
+            report_error_spanning(
+                &tokens,
                 ret,
                 "More than one struct/enum/.. declaration found",
             );
         }
+        let kw_ident = get_decl_ident(&tok[kw]).unwrap();
+        let has_body = tok[kw + 1..].iter().any(|t| {
+            matches!(t, TypeTree::Token(TokenTree::Group(g)) if g.delimiter() != Delimiter::Bracket)
+        });
+        let named = matches!(tok.get(kw + 1), Some(TypeTree::Token(TokenTree::Ident(_))));
+        // field: struct Unit, is fine, but there's nothing to make of a bare struct or a bodiless enum
+        let unit = !has_body && named && kw_ident == "struct";
+        if !has_body && !unit && ["struct", "enum", "union"].iter().any(|k| kw_ident == k) {
+            report_error(
+                Some(kw_ident.span()),
+                ret,
+                &format!(
+                    "{} declaration without a body, expected e.g. {} Name {{ … }}",
+                    kw_ident, kw_ident
+                ),
+            );
+            return;
+        }
         let mut decl = Vec::new();
         un_tree_type(tok, &mut decl);
+        let terminated = matches!(decl.last(), Some(TokenTree::Punct(p)) if p.as_char() == ';');
+        if unit && !terminated {
+            decl.push(TokenTree::Punct(Punct::new(';', Spacing::Alone)));
+        }
         let pos = decl
             .iter()
             .position(|t| matches!(t, TokenTree::Ident(kw) if is_decl_kw(kw)))
             .unwrap();
+        if let Some(field) = transparent_field(&decl, pos, ret) {
+            let (field_name, ty) = field;
+            let field_name_str = field_name
+                .as_ref()
+                .map(Ident::to_string)
+                .unwrap_or_default();
+            let path = match &field_name {
+                Some(_) => path.with_field_name(&field_name_str),
+                None => *path,
+            };
+            let name_hint = match &field_name {
+                Some(field_name) => Some(path.get_name_hint(None, field_name.span())),
+                None => name_hint.clone(),
+            };
+            recurse_through_type_list(
+                &type_tree(&ty, ret),
+                strike_attrs,
+                ret,
+                &name_hint,
+                pub_hint,
+                type_ret,
+                &path,
+            );
+            return;
+        }
         let generics = if let Some(name @ TokenTree::Ident(_)) = decl.get(pos + 1) {
             type_ret.push(name.clone());
-            recurse_through_definition(
+            strike_definition(
                 decl.into_iter().collect(),
                 strike_attrs.to_vec(),
                 pub_hint,
+                false,
+                Some((path.generics, path.where_clause)),
                 ret,
             )
         } else {
             let name = match name_hint {
-                Some(name) => TokenTree::Ident(name.clone()),
+                Some(name) => TokenTree::Ident(path.wrapped_name(name)),
                 None => {
                     report_error(
                         stream_span(decl.iter()),
@@ -559,35 +3648,154 @@ fn recurse_through_type(
             let tail = decl.drain((pos + 1)..).collect::<TokenStream>();
             let head = decl.into_iter().collect::<TokenStream>();
             let newthing = quote! {#head #name #tail};
-            let generics =
-                recurse_through_definition(newthing, strike_attrs.to_vec(), pub_hint, ret);
+            let generics = strike_definition(
+                newthing,
+                strike_attrs.to_vec(),
+                pub_hint,
+                false,
+                Some((path.generics, path.where_clause)),
+                ret,
+            );
 
             type_ret.push(name);
             generics
         };
         if let Some(generics) = generics {
-            type_ret.push(generics.tk_l_bracket.into());
-            let mut gp = generics.params.clone();
-            gp.iter_mut().for_each(|(gp, _)| {
-                *gp = GenericParam {
-                    name: gp.name.clone(),
-                    tk_prefix: gp
-                        .tk_prefix
-                        .clone()
-                        .filter(|pfx| matches!(pfx, TokenTree::Punct(_))),
-                    bound: None,
-                }
-            });
-            type_ret.extend(gp.into_token_stream());
-            type_ret.push(generics.tk_r_bracket.into());
+            type_ret.extend(generic_args(&generics));
         }
     } else {
-        un_type_tree(tok, type_ret, |g, type_ret| {
+        un_type_tree(tok, type_ret, |g, type_ret, wrapper| {
+            let path = &path.with_wrapper(wrapper);
             recurse_through_type_list(g, strike_attrs, ret, name_hint, false, type_ret, path)
         });
     }
 }
 
+/// For a declaration marked `#[structstruck::transparent]`, the name and type of its only field,
+/// which take the place of the declaration
+fn transparent_field(
+    decl: &[TokenTree],
+    pos: usize,
+    ret: &mut TokenStream,
+) -> Option<(Option<Ident>, Vec<TokenTree>)> {
+    let mut decl = decl.to_vec();
+    if !matches!(decl.get(pos + 1), Some(TokenTree::Ident(_))) {
+        // Only there to make venial happy
+        let name = Ident::new("Transparent", decl[pos].span());
+        decl.insert(pos + 1, TokenTree::Ident(name));
+    }
+    let decl = move_out_inner_attrs(decl.into_iter().collect())
+        .into_iter()
+        .collect::<Vec<_>>();
+    let transparent = decl.iter().any(|t| match t {
+        TokenTree::Group(attr) if attr.delimiter() == Delimiter::Bracket => {
+            let attr = attr.stream().into_iter().collect::<Vec<_>>();
+            matches!(&attr[..], [TokenTree::Ident(crat), TokenTree::Punct(c1), TokenTree::Punct(c2), TokenTree::Ident(name)]
+                if crat == env!("CARGO_CRATE_NAME") && c1.as_char() == ':' && c2.as_char() == ':' && name == "transparent")
+        }
+        _ => false,
+    });
+    if !transparent {
+        return None;
+    }
+    let span = stream_span(decl.iter());
+    let field = match parse_declaration(decl.into_iter().collect()) {
+        Ok(Declaration::Struct(s)) => match &s.fields {
+            StructFields::Named(n) if n.fields.len() == 1 => n
+                .fields
+                .iter()
+                .next()
+                .map(|(f, _)| (Some(f.name.clone()), f.ty.tokens.clone())),
+            StructFields::Tuple(t) if t.fields.len() == 1 => t
+                .fields
+                .iter()
+                .next()
+                .map(|(f, _)| (None, f.ty.tokens.clone())),
+            _ => None,
+        },
+        _ => None,
+    };
+    if field.is_none() {
+        report_error(
+            span,
+            ret,
+            "#[structstruck::transparent]: only structs with exactly one field can be inlined",
+        );
+    }
+    field
+}
+
+/// Whether any declaration in the type lacks an explicit name
+fn uses_name_hint(tok: &[TypeTree]) -> bool {
+    let nested = tok.iter().any(|t| match t {
+        TypeTree::Group(_, g, _) => uses_name_hint(g),
+        _ => false,
+    });
+    nested
+        || decl_kws(tok)
+            .any(|(i, _)| !matches!(tok.get(i + 1), Some(TypeTree::Token(TokenTree::Ident(_)))))
+}
+
+/// Declaration keywords and their position, except for the fn in fn pointer types like `fn(u8) -> u8`
+fn decl_kws<'a>(tok: &'a [TypeTree]) -> impl Iterator<Item = (usize, &'a Ident)> {
+    tok.iter().enumerate().filter_map(|(i, t)| {
+        let kw = get_decl_ident(t)?;
+        let fn_pointer = kw == "fn"
+            && matches!(tok.get(i + 1), Some(TypeTree::Token(TokenTree::Group(g))) if g.delimiter() == Delimiter::Parenthesis);
+        (!fn_pointer).then_some((i, kw))
+    })
+}
+
+/// Generic parameters without their defaults, which aren't allowed in `impl<…>`
+fn impl_params(generics: &GenericParamList) -> GenericParamList {
+    let mut generics = generics.clone();
+    for (param, _) in generics.params.iter_mut() {
+        // The = in place of the colon is from restore_generic_defaults
+        if matches!(&param.bound, Some(bound) if bound.tk_colon.as_char() == '=') {
+            param.bound = None;
+        }
+        if let Some(bound) = &mut param.bound {
+            let mut depth = 0;
+            let tokens = &bound.tokens;
+            let eq = tokens.iter().enumerate().position(|(i, t)| match t {
+                TokenTree::Punct(p) if p.as_char() == '<' => {
+                    depth += 1;
+                    false
+                }
+                TokenTree::Punct(p) if p.as_char() == '>' && !is_arrow(tokens, i) => {
+                    depth -= 1;
+                    false
+                }
+                TokenTree::Punct(p) => depth == 0 && p.as_char() == '=',
+                _ => false,
+            });
+            if let Some(eq) = eq {
+                bound.tokens.truncate(eq);
+            }
+        }
+    }
+    generics
+}
+
+/// Turns generic parameters `<'a, T: Bound, const N: usize>` into arguments `<'a, T, N>`
+fn generic_args(generics: &GenericParamList) -> TokenStream {
+    let mut gp = generics.params.clone();
+    gp.iter_mut().for_each(|(gp, _)| {
+        *gp = GenericParam {
+            name: gp.name.clone(),
+            tk_prefix: gp
+                .tk_prefix
+                .clone()
+                .filter(|pfx| matches!(pfx, TokenTree::Punct(_))),
+            bound: None,
+        }
+    });
+    let (l, r) = (&generics.tk_l_bracket, &generics.tk_r_bracket);
+    quote!(#l #gp #r)
+}
+
+/// Only looks at plain tokens: Anything in a {block} is an expression or statement,
+/// e.g. a const generic argument, and must not be extracted.
 fn get_decl_ident<'a>(t: &'a TypeTree) -> Option<&'a Ident> {
     match t {
         TypeTree::Token(TokenTree::Ident(ref kw)) if is_decl_kw(kw) => Some(kw),
@@ -596,43 +3804,161 @@ fn get_decl_ident<'a>(t: &'a TypeTree) -> Option<&'a Ident> {
 }
 
 fn un_tree_type(tok: &[TypeTree], type_ret: &mut Vec<TokenTree>) {
-    un_type_tree(tok, type_ret, un_tree_type)
+    un_type_tree(tok, type_ret, |g, type_ret, _| un_tree_type(g, type_ret))
 }
 
 fn un_type_tree(
     tok: &[TypeTree],
     type_ret: &mut Vec<TokenTree>,
-    mut f: impl FnMut(&[TypeTree], &mut Vec<TokenTree>),
+    mut f: impl FnMut(&[TypeTree], &mut Vec<TokenTree>, Option<&Ident>),
 ) {
-    for tt in tok.iter() {
+    for (i, tt) in tok.iter().enumerate() {
         match tt {
             TypeTree::Group(o, g, c) => {
                 type_ret.push(TokenTree::Punct((*o).clone()));
-                f(g, type_ret);
+                // The type the arguments are for, e.g. Box in Box<…>
+                let wrapper = match i.checked_sub(1).map(|i| &tok[i]) {
+                    Some(TypeTree::Token(TokenTree::Ident(wrapper))) => Some(wrapper),
+                    _ => None,
+                };
+                f(g, type_ret, wrapper);
                 if let Some(c) = c {
                     type_ret.push(TokenTree::Punct((*c).clone()));
                 }
             }
+            TypeTree::Token(TokenTree::Group(g))
+                if g.delimiter() == Delimiter::Bracket
+                    || is_type_parens(g, i.checked_sub(1).map(|i| &tok[i])) =>
+            {
+                // The element of a slice or array is a type position, too, and so are tuple elements and arguments.
+                let inner = g.stream().into_iter().collect::<Vec<_>>();
+                // The length of an array is an expression, possibly with < or >, and is left alone
+                let len = match g.delimiter() {
+                    Delimiter::Bracket => inner
+                        .iter()
+                        .position(|t| matches!(t, TokenTree::Punct(p) if p.as_char() == ';')),
+                    _ => None,
+                };
+                let (inner, len) = inner.split_at(len.unwrap_or(inner.len()));
+                let mut errors = TokenStream::new();
+                let inner_tree = type_tree(inner, &mut errors);
+                if !errors.is_empty() {
+                    // Not a type, apparently. Leave it alone.
+                    type_ret.push(TokenTree::Group(g.clone()));
+                    continue;
+                }
+                let mut elem = vec![];
+                f(&inner_tree, &mut elem, None);
+                elem.extend_from_slice(len);
+                let mut group = Group::new(g.delimiter(), elem.into_iter().collect());
+                group.set_span(g.span());
+                type_ret.push(TokenTree::Group(group));
+            }
             TypeTree::Token(t) => type_ret.push((*t).clone()),
         }
     }
 }
 
+/// Tuples and the arguments of Fn(…) or fn(…), but not the arguments of a macro!(…)
+fn is_type_parens(g: &Group, prev: Option<&TypeTree>) -> bool {
+    g.delimiter() == Delimiter::Parenthesis && prev.and_then(|p| get_tt_punct(p, '!')).is_none()
+}
+
 #[cfg_attr(test, derive(Debug))]
 pub(crate) enum TypeTree<'a> {
     Group(&'a Punct, Vec<TypeTree<'a>>, Option<&'a Punct>),
     Token(&'a TokenTree),
 }
 
+/// A string literal with an indented rendering of the [`TypeTree`] of `input`
+#[cfg(any(test, feature = "internals"))]
+pub(crate) fn debug_type_tree(input: TokenStream, ret: &mut TokenStream) {
+    fn render(tok: &[TypeTree], indent: usize, out: &mut String) {
+        for t in tok {
+            match t {
+                TypeTree::Group(open, inner, close) => {
+                    out.push_str(&format!("{:indent$}{}\n", "", open, indent = indent));
+                    render(inner, indent + 2, out);
+                    let close = close.map_or("(unclosed)".to_owned(), |c| c.to_string());
+                    out.push_str(&format!("{:indent$}{}\n", "", close, indent = indent));
+                }
+                TypeTree::Token(t) => {
+                    out.push_str(&format!("{:indent$}{}\n", "", t, indent = indent));
+                }
+            }
+        }
+    }
+    let input = input.into_iter().collect::<Vec<_>>();
+    let mut out = String::new();
+    render(&type_tree(&input, ret), 0, &mut out);
+    proc_macro2::Literal::string(&out).to_tokens(ret);
+}
+
+/// The declarations generated from `input`, as an array of `(name, source)` string pairs.
+/// Diagnostics are emitted before the array, impls and other items are left out.
+#[cfg(any(test, feature = "internals"))]
+pub(crate) fn generated_declarations(input: TokenStream, ret: &mut TokenStream) {
+    let mut generated = TokenStream::new();
+    recurse_through_definition(flatten_empty_groups(input), vec![], false, &mut generated);
+    let generated = generated.into_iter().collect::<Vec<_>>();
+    let mut diagnostics = TokenStream::new();
+    let mut declarations = vec![];
+    let mut start = 0;
+    let mut depth = 0usize;
+    for (i, t) in generated.iter().enumerate() {
+        let end = match t {
+            TokenTree::Punct(p) if p.as_char() == '<' => {
+                depth += 1;
+                false
+            }
+            TokenTree::Punct(p) if p.as_char() == '>' && !is_arrow(&generated, i) => {
+                depth = depth.saturating_sub(1);
+                false
+            }
+            TokenTree::Punct(p) => p.as_char() == ';' && depth == 0,
+            // Bodies, but not { expressions } in generics
+            TokenTree::Group(g) => g.delimiter() == Delimiter::Brace && depth == 0,
+            _ => false,
+        };
+        if !end {
+            continue;
+        }
+        let item = &generated[start..=i];
+        start = i + 1;
+        let has = |name: &str| {
+            item.iter()
+                .any(|t| matches!(t, TokenTree::Ident(i) if i == name))
+        };
+        match generated_types(item).next() {
+            Some((_, name)) if !has("impl") => {
+                let source = item.iter().cloned().collect::<TokenStream>().to_string();
+                declarations.push((name.to_string(), source));
+            }
+            // compile_error!(…); and the warnings from structstruck::debug and the like
+            _ if has("compile_error")
+                || has("strikethrough_used")
+                || matches!(item, [TokenTree::Ident(c), TokenTree::Ident(u), ..] if c == "const" && u == "_") =>
+            {
+                diagnostics.extend(item.iter().cloned())
+            }
+            _ => (),
+        }
+    }
+    let declarations = declarations
+        .iter()
+        .map(|(name, source)| quote!((#name, #source)));
+    quote!({ #diagnostics [#(#declarations),*] }).to_tokens(ret);
+}
+
 pub(crate) fn type_tree<'a>(args: &'a [TokenTree], ret: &'_ mut TokenStream) -> Vec<TypeTree<'a>> {
     let mut stac = vec![];
     let mut current = vec![];
-    for tt in args {
+    for (i, tt) in args.iter().enumerate() {
         match tt {
-            TokenTree::Punct(open) if open.as_char() == '<' => {
+            TokenTree::Punct(open) if open.as_char() == '<' && !is_comparison(args, i) => {
                 stac.push((open, mem::take(&mut current)));
             }
-            TokenTree::Punct(close) if close.as_char() == '>' => {
+            TokenTree::Punct(close) if close.as_char() == '>' && !is_arrow(args, i) => {
                 if let Some((open, parent)) = stac.pop() {
                     let child = mem::replace(&mut current, parent);
                     current.push(TypeTree::Group(open, child, Some(close)));
@@ -652,6 +3978,23 @@ pub(crate) fn type_tree<'a>(args: &'a [TokenTree], ret: &'_ mut TokenStream) ->
     current
 }
 
+/// Whether the < at args[pos] is a comparison, not the start of generic arguments.
+/// That's the case if it follows a value (a literal or a parenthesized expression) or is a <=.
+/// `N < M` can't be told apart from an unclosed `Vec<struct {…}`, but only the latter
+/// is in a type position, comparisons are in a `{ block }` or an array length, which aren't looked into.
+fn is_comparison(args: &[TokenTree], pos: usize) -> bool {
+    let prev = pos.checked_sub(1).map(|prev| &args[prev]);
+    let next = args.get(pos + 1);
+    matches!(prev, Some(TokenTree::Literal(_) | TokenTree::Group(_)))
+        || matches!(next, Some(TokenTree::Punct(eq)) if eq.as_char() == '=')
+}
+
+/// Whether the > at args[pos] is part of a ->, e.g. in `dyn Fn(u8) -> u8`
+fn is_arrow(args: &[TokenTree], pos: usize) -> bool {
+    let prev = pos.checked_sub(1).map(|prev| &args[prev]);
+    matches!(prev, Some(TokenTree::Punct(dash)) if dash.as_char() == '-' && dash.spacing() == Spacing::Joint)
+}
+
 fn is_decl_kw(kw: &Ident) -> bool {
     kw == "struct"
         || kw == "enum"
@@ -662,6 +4005,30 @@ fn is_decl_kw(kw: &Ident) -> bool {
         || kw == "trait"
 }
 
+/// Reports an error at the value of `attr`, or at its `#` if it has none
+fn report_attr_error(attr: &Attribute, ret: &mut TokenStream, error: &str) {
+    report_error(
+        stream_span(attr.get_value_tokens().iter()).or(Some(attr.tk_hash.span())),
+        ret,
+        error,
+    );
+}
+
+/// Like [`report_error`], but underlines all of `tokens`, not just where they start
+fn report_error_spanning(tokens: &[TokenTree], ret: &mut TokenStream, error: &str) {
+    let (first, last) = match (tokens.first(), tokens.last()) {
+        (Some(first), Some(last)) => (first.span(), last.span()),
+        _ => return report_error(None, ret, error),
+    };
+    let error = format!("{} error: {}", env!("CARGO_PKG_NAME"), error);
+    // rustc reports from the span of compile_error to that of its arguments
+    let mut args = Group::new(Delimiter::Parenthesis, quote!(#error));
+    args.set_span(last);
+    quote_spanned!(first => compile_error!).to_tokens(ret);
+    args.to_tokens(ret);
+    quote_spanned!(last => ;).to_tokens(ret);
+}
+
 fn report_error(span: Option<Span>, ret: &mut TokenStream, error: &str) {
     let error = format!(
         "{} error: {} - starting from:",
@@ -679,6 +4046,43 @@ fn report_error(span: Option<Span>, ret: &mut TokenStream, error: &str) {
     }
 }
 
+/// `name, #[attr]…` defines a `macro_rules! name` that calls `strike!` with the attributes prepended
+pub(crate) fn preset(input: TokenStream, ret: &mut TokenStream) {
+    let input = input.into_iter().collect::<Vec<_>>();
+    let (name, attrs) = match &input[..] {
+        [TokenTree::Ident(name), TokenTree::Punct(comma), attrs @ ..] if comma.as_char() == ',' => {
+            (name, attrs)
+        }
+        _ => {
+            return report_error(
+                stream_span(input.iter()),
+                ret,
+                "structstruck::preset!: expected a macro name, a comma, and attributes",
+            )
+        }
+    };
+    let is_attrs = attrs.chunks(2).all(|attr| {
+        matches!(attr, [TokenTree::Punct(hash), TokenTree::Group(g)]
+            if hash.as_char() == '#' && g.delimiter() == Delimiter::Bracket)
+    });
+    if !is_attrs {
+        return report_error(
+            stream_span(attrs.iter()),
+            ret,
+            "structstruck::preset!: expected only #[attributes] after the macro name",
+        );
+    }
+    let attrs = attrs.iter().cloned().collect::<TokenStream>();
+    quote! {
+        macro_rules! #name {
+            ($($t:tt)*) => {
+                ::structstruck::strike! { #attrs $($t)* }
+            };
+        }
+    }
+    .to_tokens(ret);
+}
+
 pub fn flatten_empty_groups(ts: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
     ts.into_iter()
         .flat_map(|tt| match tt {
@@ -114,6 +114,22 @@
 //! }
 //! ```
 //!
+//! Functions can declare the types of their parameters and their return type in place.
+//! Qualifiers like `const`, `async`, `unsafe`, or `extern "C"` are kept on the function.
+//! ```no_run
+//! structstruck::strike! {
+//!     async fn load(options: struct { retries: u8 }) -> struct Loaded { bytes: Vec<u8> } {
+//!         Loaded { bytes: vec![options.retries] }
+//!     }
+//! }
+//! ```
+//! Parameters are named after the parameter (`Options`), return types after the function.
+//! `structstruck::each` attributes are only applied to the declared types, not to the function.
+//!
+//! `impl` blocks work, too: declarations in method signatures are emitted before the `impl`,
+//! named after the parameter or method. Method bodies and other items are left alone.
+//! Since the declarations end up outside of the `impl`, `Self` in them means the declared type itself.
+//!
 //! #### Substructs in generics
 //! Declarations may appear inside generics arguments. (It works "as you would expect".)
 //! ```no_run
@@ -149,7 +165,103 @@
 //!     b: Result<Then, Else>,
 //! }
 //! ```
-//! (The structs themselves being generic is not supported yet(?).)
+//!
+//! The same goes for the element type of slices and arrays, e.g. `Box<[struct Item { v: u8 }]>` or `[struct Item { v: u8 }; 4]`.
+//! Tuples and closure types work, too: `Box<dyn Fn(struct Arg { … }) -> struct { … }>`.
+//! An anonymous return type is named after the field with `Output` appended.
+//!
+//! Declarations may also appear as defaults or bounds of generic parameters, or in where clauses.
+//! Their name is made from the name of the declaration and the parameter, e.g.
+//! `struct Outer<T = struct { x: u8 }>` generates `OuterT`,
+//! and `fn load<T: Into<struct { x: u8 }>>()` generates `LoadT`.
+//!
+//! If a nested struct declares generic parameters that none of its fields use,
+//! a `_phantom: PhantomData<…>` field is appended to keep the compiler happy.
+//! This can be disabled with `#[structstruck::no_phantom_data]`.
+//! The declaration at the top level is left as written.
+//!
+//! Nested declarations without generic parameters of their own take over those of
+//! the enclosing declaration that they use, at any depth:
+//! ```
+//! structstruck::strike! {
+//!     struct A<T> {
+//!         b: struct {
+//!             c: struct {
+//!                 t: T,
+//!             },
+//!             flag: bool,
+//!         },
+//!     }
+//! }
+//! ```
+//! generates `struct C<T> { t: T }`, `struct B<T> { c: C<T>, flag: bool }`, and `A<T> { b: B<T> }`.
+//! Bounds in the parameter list are copied along, and so are the predicates of a where clause
+//! that only name parameters which come along.
+//!
+//! #### Visibility
+//! Nested declarations are made `pub` if the field they are declared in is `pub`,
+//! and keep the visibility they were declared with otherwise,
+//! also inside generic arguments, e.g. `Arc<pub(crate) struct Inner { … }>`.
+//! `#[structstruck::vis(…)]` sets the visibility of a single declaration, regardless of all that.
+//! ```no_run
+//! structstruck::strike! {
+//!     pub(crate) struct Outer {
+//!         pub inner: struct {
+//!             #![structstruck::vis(pub(crate))]
+//!             value: usize
+//!         }
+//!     }
+//! }
+//! ```
+//! (`#[structstruck::vis()]` makes a declaration private, and so does `#[structstruck::private]`.)
+//! Put `#[structstruck::private]` on a field to keep the declarations in it from becoming `pub`,
+//! e.g. in the variants of a `pub enum`, or `#[structstruck::skip_pub_propagation]` on a variant
+//! to do that for all of its fields.
+//!
+//! `#[structstruck::default_vis(…)]` gives all declarations nested in the one it is on a visibility,
+//! e.g. to keep them `pub(crate)` even in `pub` fields. `#[structstruck::vis(…)]` still wins over it.
+//!
+//! `#[structstruck::reexport(name)]` additionally emits a module `name` that re-exports
+//! all `pub` and `pub(crate)` types from the declaration, e.g. as a facade for downstream users.
+//! Private types are skipped.
+//! ```no_run
+//! mod shapes {
+//!     structstruck::strike! {
+//!         #[structstruck::reexport(types)]
+//!         pub struct Outer {
+//!             pub inner: struct { value: usize },
+//!             hidden: struct { value: usize },
+//!         }
+//!     }
+//! }
+//! // shapes::types::Inner and shapes::types::Outer can be used, shapes::types::Hidden can't
+//! ```
+//! The module re-exports through `super::`, so it needs to be in a module, not in a function.
+//! If the declaration isn't where `super` of the new module points, give a path instead:
+//! `reexport(crate::shapes::types)` re-exports through `crate::shapes::`.
+//!
+//! `#[structstruck::alias_as(Name)]` emits a `type Name = Generated;` next to the declaration,
+//! with its visibility and generics. Associated types can't be declared outside of an `impl`,
+//! so bind them there: `type Item = Name;`.
+//! ```no_run
+//! structstruck::strike! {
+//!     pub struct Outer<T> {
+//!         pub item: struct {
+//!             #![structstruck::alias_as(OuterItem)]
+//!             value: T,
+//!         }
+//!     }
+//! }
+//! // pub type OuterItem<T> = Item<T>;
+//! ```
+//!
+//! #### Constants and statics
+//! The types of `const` and `static` items may contain declarations, too.
+//! ```no_run
+//! structstruck::strike! {
+//!     const DEFAULT: struct Config { retries: u8 } = Config { retries: 3 };
+//! }
+//! ```
 //!
 //! #### Attributes
 //! Applying attributes (or doc comments) to a single inner struct would be syntactically awkward:
@@ -175,8 +287,15 @@
 //! }
 //! ```
 //!
+//! `#[cfg(…)]` attributes on a field are also applied to the declarations in its type.
+//! In tuple structs and variants, `#[derive(…)]`, `#[repr(…)]` and doc comments in front of a declaration,
+//! like in `Wrapper(#[repr(C)] struct Inner { … })`, go to that declaration instead of the field.
+//! Other attributes, like `#[serde(…)]`, stay on the field.
+//!
 //! To quickly apply attributes to all declarations, attributes can be wrapped in the `#[structstruck::each[…]]`
 //! pseudoattribute.
+//! (`#[structstruck::each(…)]` works, too.
+//! The deprecated `#[strikethrough[…]]` also still works, `#[structstruck::allow_strikethrough]` silences its warning.)
 //! ```no_run
 //! structstruck::strike! {
 //!     // It's strikethrough[…], not strikethrough(…)
@@ -199,6 +318,183 @@
 //! println!("{:#?}", Parent { ..todo!("value skipped for brevity") });
 //! ```
 //!
+//! The attributes from `structstruck::each` are placed after the attributes of nested declarations,
+//! in case some attribute macro cares about order.
+//! `derive(…)` and `serde(…)`, also inside `cfg_attr(…)`, are not applied to type aliases,
+//! since they can't derive anything. The rest of such a `cfg_attr(…)` is kept.
+//! Writing `#[derive(…)]` on a type alias yourself is an error.
+//! Enums that end up internally tagged with `serde(tag = "…")` are checked for tuple variants,
+//! which serde doesn't support.
+//!
+//! `each` can be limited to some levels of nesting or some kinds of declarations:
+//! ```no_run
+//! structstruck::strike! {
+//!     #[structstruck::each(depth = 1)[repr(C)]]
+//!     #[structstruck::each(enum)[derive(Copy, Clone)]]
+//!     #[structstruck::each[derive(Debug)]]
+//!     struct Outer {
+//!         inner: struct {
+//!             innermost: struct {},
+//!             kind: enum { A, B },
+//!         }
+//!     }
+//! }
+//! ```
+//! Here, `Outer` and `Inner` are `repr(C)`, but `Innermost` isn't. `Kind` is `Copy`, and all of them are `Debug`.
+//! (`depth = 0` only applies to the declaration itself.)
+//!
+//! `#[structstruck::derive_here(…)]` derives traits on just the declaration it is on,
+//! leaving out those that are already derived, e.g. through `each`.
+//! ```no_run
+//! structstruck::strike! {
+//!     #[structstruck::each[derive(Debug)]]
+//!     struct Outer {
+//!         inner: struct {
+//!             #![structstruck::derive_here(Debug, Hash)]
+//!             value: usize
+//!         }
+//!     }
+//! }
+//! ```
+//! To pick out a declaration from the top, name it in `#[structstruck::derive_for(…)]`:
+//! ```no_run
+//! structstruck::strike! {
+//!     #[structstruck::derive_for(Inner => Clone, Debug)]
+//!     struct Outer {
+//!         inner: struct { value: usize },
+//!         other: struct { value: usize },
+//!     }
+//! }
+//! ```
+//! makes only `Inner` `Clone` and `Debug`.
+//!
+//! `#[structstruck::each_if(copy)[…]]` only applies where all fields are known to be `Copy`:
+//! primitives, shared references, tuples and arrays of those, and nested declarations that derive `Copy`.
+//! Anything else, generic parameters included, counts as not `Copy`.
+//! ```no_run
+//! structstruck::strike! {
+//!     #[structstruck::each_if(copy)[derive(Clone, Copy)]]
+//!     struct Outer {
+//!         pos: struct { x: f32, y: f32 },
+//!         name: String,
+//!     }
+//! }
+//! ```
+//! makes `Pos` `Copy`, but not `Outer`.
+//!
+//! Traits that can't be derived can be implemented for every struct, enum, and union in the tree
+//! with `#[structstruck::each_impl(Trait)]`, which emits an empty `impl Trait for Generated {}`.
+//! Items for the `impl` can be given in braces after the trait, e.g.
+//! `#[structstruck::each_impl(Named { const NAME: &'static str = "generated"; })]`.
+//!
+//! A field whose type isn't `Debug` can be marked `#[structstruck::debug_skip]`.
+//! If the struct derives `Debug`, the derive is replaced by an `impl Debug` that leaves the field out.
+//! ```no_run
+//! structstruck::strike! {
+//!     #[structstruck::each[derive(Debug)]]
+//!     struct Outer {
+//!         inner: struct {
+//!             value: usize,
+//!             #[structstruck::debug_skip]
+//!             callback: fn(),
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! #### Common fields
+//! `#[structstruck::each_field(…)]` adds a field to every struct with named fields in the tree.
+//! A subtree can opt out with `#[structstruck::no_each_field]`.
+//! ```no_run
+//! structstruck::strike! {
+//!     #[structstruck::each_field(id: u64)]
+//!     struct Outer {
+//!         inner: struct { value: usize }
+//!     }
+//! }
+//! ```
+//! Both `Outer` and `Inner` get a field `id: u64`.
+//!
+//! #### Field attributes on nested types
+//! Attributes on a field stay on the field. To also put some of them on the types declared in the field,
+//! name them in `#[structstruck::inherit_field_attrs(…)]`, which applies to the whole tree:
+//! ```no_run
+//! structstruck::strike! {
+//!     #[structstruck::inherit_field_attrs(allow, doc)]
+//!     struct Outer {
+//!         /// Only read in tests
+//!         #[allow(dead_code)]
+//!         inner: struct { value: Vec<usize> },
+//!     }
+//! }
+//! ```
+//! Both the field `inner` and the struct `Inner` get the doc comment and `#[allow(dead_code)]`.
+//!
+//! #### Unused nested types
+//! Nested types that are only used behind a `cfg` or through their parent may trigger `dead_code` warnings.
+//! `#[structstruck::allow_dead_code]` puts `#[allow(dead_code)]` on all declarations nested in the one it is on,
+//! but not on that one itself.
+//!
+//! #### Sorted fields
+//! `#[structstruck::sort_fields]` sorts the fields of every struct with named fields in the tree by name,
+//! e.g. for formats where the serialized layout follows the field order.
+//! Attributes and doc comments move with their fields, tuple structs and enum variants keep their order.
+//!
+//! #### Inlining
+//! A nested struct with a single field can be marked `#[structstruck::transparent]`
+//! to not generate it at all and use the type of its field in its place.
+//! ```no_run
+//! structstruck::strike! {
+//!     struct Outer {
+//!         inner: #[structstruck::transparent] struct Inner { value: u32 },
+//!     }
+//! }
+//! ```
+//! generates only `struct Outer { inner: u32 }`.
+//!
+//! #### Accessors
+//! For tuple structs with a single field, `#[structstruck::accessors]` generates a method to get at that field.
+//! It's named after the field's type if that is declared in place, `value` otherwise.
+//! ```no_run
+//! structstruck::strike! {
+//!     #[structstruck::each[structstruck::accessors]]
+//!     pub struct Meters(pub struct Inner(f64));
+//! }
+//! ```
+//! generates `Meters::inner(&self) -> &Inner` and `Inner::value(&self) -> &f64`.
+//!
+//! #### Conversions for enum variants
+//! With `#[structstruck::variant_conversions]` on an enum, each variant that wraps a single declaration
+//! gets a `From` impl to make the enum from the inner type, and a `TryFrom` impl for the way back.
+//! ```no_run
+//! structstruck::strike! {
+//!     #[structstruck::variant_conversions]
+//!     enum Shape {
+//!         Circle(struct { radius: f64 }),
+//!         Square(struct { side: f64 }),
+//!         Empty,
+//!     }
+//! }
+//! let shape = Shape::from(Circle { radius: 1.0 });
+//! assert!(Square::try_from(shape).is_err());
+//! ```
+//!
+//! #### Default values
+//! If deriving `Default` isn't an option because some field isn't `Default`,
+//! `#[structstruck::default = …]` on fields generates an `impl Default` instead.
+//! Fields without the attribute use `Default::default()`.
+//! ```no_run
+//! structstruck::strike! {
+//!     struct Outer {
+//!         inner: struct {
+//!             #[structstruck::default = 42]
+//!             answer: u32,
+//!             name: String,
+//!         }
+//!     }
+//! }
+//! ```
+//!
 //! #### Avoiding name collisions
 //! If you want include the parent struct name (or parent enum name and variant name)
 //! in the name of the child struct, add `#[structstruck::long_names]` to the struct.
@@ -234,9 +530,156 @@
 //!
 //! This is useful to prevent collisions when using the same field name multiple times or a type with the same name as a field exists.
 //!
+//! A subtree can opt back out with `#[structstruck::short_names]`, the most specific setting wins.
+//!
+//! #### Prefixes and suffixes
+//! `#[structstruck::name_prefix("…")]` and `#[structstruck::name_suffix("…")]` are added to the names of
+//! all declarations in the tree that are named by `structstruck`. Explicitly named declarations are left alone.
+//! ```no_run
+//! structstruck::strike! {
+//!     #[structstruck::name_suffix("Dto")]
+//!     struct User {
+//!         address: struct { street: String },
+//!         role: enum Role { Admin, Guest },
+//!     }
+//! }
+//! ```
+//! generates `AddressDto` and `Role`.
+//!
+//! For names that depend on the field name, `#[structstruck::name_map(…)]` takes a list of rules.
+//! A rule matches a field name by `prefix("…")`, `suffix("…")`, or the whole `name("…")`,
+//! and makes a name from the rest of it with `prefix("…")`, `suffix("…")`, or `replace("…")`.
+//! The first matching rule wins, rules from inner declarations are tried first.
+//! ```no_run
+//! structstruck::strike! {
+//!     #[structstruck::name_map(suffix("_cfg") => suffix("Config"), name("data") => replace("Payload"))]
+//!     struct Server {
+//!         db_cfg: struct { url: String },
+//!         data: struct { bytes: Vec<u8> },
+//!     }
+//! }
+//! ```
+//! generates `DbConfig` and `Payload`.
+//!
+//! #### Numbering tuple fields
+//! Names for declarations in tuple fields get the field index appended, except for the first field.
+//! This can be changed with `#[structstruck::numbering(…)]` on the parent declaration:
+//!  * `skip_first`: the default
+//!  * `always`: number every field, starting with `0`
+//!  * `on_collision`: only number a field if an earlier field already used the bare name
+//!  * `never`: never number, you'll have to make sure the names don't collide
+//! ```no_run
+//! structstruck::strike! {
+//!     #[structstruck::numbering(on_collision)]
+//!     enum Parent {
+//!         Tuple(u8, struct { c: u8 }, struct { d: u8 })
+//!     }
+//! }
+//! ```
+//! generates `Tuple` and `Tuple2`.
+//! With `#[structstruck::number_separator("_")]`, that would be `Tuple_2`.
+//! The number goes before a `name_suffix`, and the same applies to several anonymous
+//! declarations in one generic type like `Result<struct { … }, struct { … }>`.
+//!
+//! #### Names from doc comments
+//! Fields with generic names like `data` make for bad type names.
+//! With `#[structstruck::name_from_doc]`, the first line of a field's doc comment is used instead,
+//! as long as it is a plain phrase of words.
+//! ```no_run
+//! structstruck::strike! {
+//!     #[structstruck::name_from_doc]
+//!     struct Outer {
+//!         /// Storage settings
+//!         data: struct { size: usize }
+//!     }
+//! }
+//! ```
+//! will name the inner struct `StorageSettings`.
+//! If the doc comment is not a plain phrase, the field name is used as usual.
+//!
+//! #### Names from wrapper types
+//! With `#[structstruck::wrapper_names]`, declarations in the arguments of a generic type
+//! get that type's name in front, e.g. `engine: Box<struct { … }>` generates `BoxEngine`.
+//! Only the innermost wrapper counts, `Option<Vec<struct { … }>>` is a `Vec…`.
+//!
+//! Anonymous declarations in both arguments of a map are numbered like any others,
+//! `HashMap<struct { … }, struct { … }>` in a field `entries` generates `Entries` and `Entries1`.
+//! With `#[structstruck::map_names]`, the arguments of types named `…Map` get `Key` and `Value`
+//! appended instead, i.e. `EntriesKey` and `EntriesValue`.
+//!
+//! #### Names as written
+//! `#[structstruck::raw_names]` skips the conversion to PascalCase for the whole tree,
+//! e.g. for FFI glue that expects other names: `point_xy: struct { … }` generates `struct point_xy`.
+//! Long names are joined with `_`, and keywords like `type` become raw identifiers (`r#type`).
+//!
+//! #### Naming configuration
+//! `#[structstruck::config(…)]` sets several naming options at once for the whole tree:
+//! `separator = "_"` joins long names with the given string, `case = "raw"` (or `"pascal"`) picks
+//! whether names are converted, and `prefix`, `suffix`, and `number_separator` work like their
+//! attribute counterparts.
+//! ```
+//! structstruck::strike! {
+//!     #[structstruck::config(separator = "_")]
+//!     #[structstruck::long_names]
+//!     struct Outer {
+//!         inner: struct {},
+//!     }
+//! }
+//! let _ = Outer_Inner {};
+//! ```
+//!
+//! #### Order of declarations
+//! Nested declarations are emitted before the declaration that contains them.
+//! With `#[structstruck::emit(after)]`, they come after it instead,
+//! which some other macros need. (`#[structstruck::emit(before)]` is the default.)
+//! Use `#[structstruck::each[structstruck::emit(after)]]` to reverse the order throughout.
+//!
+//! #### Inside modules
+//! The generated declarations end up in the module `strike!` is invoked in, next to each other,
+//! and only refer to each other by name (or through `super::` from a `reexport` module).
+//! Nothing needs to know the module's path, they can be used like any other item in it:
+//! ```
+//! mod outer {
+//!     pub mod config {
+//!         structstruck::strike! {
+//!             #[structstruck::reexport(types)]
+//!             pub struct Config {
+//!                 pub db: struct { pub url: String },
+//!             }
+//!         }
+//!     }
+//!     pub fn default_db() -> config::Db {
+//!         config::Db { url: String::new() }
+//!     }
+//! }
+//! fn main() {
+//!     let db: outer::config::types::Db = outer::default_db();
+//!     let _ = outer::config::Config { db };
+//! }
+//! ```
+//!
+//! #### Debugging
+//! To see what `structstruck` makes of your declarations without reaching for `cargo expand`,
+//! add `#[structstruck::debug]`. The generated code will be shown in a warning.
+//! ```no_run
+//! structstruck::strike! {
+//!     #[structstruck::debug]
+//!     struct Outer {
+//!         inner: struct { value: usize }
+//!     }
+//! }
+//! ```
+//! When moving existing code to (or away from) `structstruck`,
+//! `#[structstruck::generate_only]` emits the nested declarations, but leaves the declaration it is on as written.
+//! That won't compile until the nested declarations in it are replaced by their names,
+//! but together with `debug`, it shows the generated types, ready to be copied out.
+//!
+//! #### Limiting the number of types
+//! `#[structstruck::max_types(N)]` makes it an error to generate more than `N` types,
+//! counting the declaration it is on. The error names the first type over the limit.
+//!
 //! ### Missing features, limitations
 //!  * You can't exclude subtrees from `#[structstruck::each[…]]`.
-//!  * Generic parameter constraints need to be repeated for each struct.
 //!  * Usage error handling is minimal, e.g.:
 //!  * All substructs will be linearized directly next to the parent struct - without any namespacing or modules.  
 //!    Would be interesting to support `foo: struct foo::Foo {…}` or some automatic version of that.
@@ -259,3 +702,85 @@ pub fn strike(item: proc_macro::TokenStream) -> proc_macro::TokenStream {
     imp::recurse_through_definition(item, vec![], false, &mut ret);
     ret.into()
 }
+
+/// Like [`strike!`], but only emits the nested declarations
+///
+/// The outermost declaration is dropped, so you can write it yourself somewhere else.
+/// ```no_run
+/// structstruck::strike_children! {
+///     struct Outer {
+///         inner: struct { value: usize }
+///     }
+/// }
+/// // Only Inner exists now
+/// struct Outer {
+///     inner: Inner,
+/// }
+/// ```
+#[proc_macro]
+pub fn strike_children(item: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let mut ret = Default::default();
+    let item = imp::flatten_empty_groups(item.into());
+    imp::recurse_through_children(item, &mut ret);
+    ret.into()
+}
+
+/// Defines a macro that works like [`strike!`], with some attributes always added
+///
+/// Saves repeating the same `#[structstruck::each[…]]` in every invocation.
+/// ```no_run
+/// structstruck::preset! {
+///     strike_debug,
+///     #[structstruck::each[derive(Debug)]]
+/// }
+/// strike_debug! {
+///     struct Outer {
+///         inner: struct { value: usize }
+///     }
+/// }
+/// ```
+/// The defined macro is a `macro_rules!` macro,
+/// so it can only be used after the preset, or after `#[macro_use]` on the module that contains it.
+#[proc_macro]
+pub fn preset(item: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let mut ret = Default::default();
+    imp::preset(item.into(), &mut ret);
+    ret.into()
+}
+
+/// Shows how a type expression is split up into generic argument groups, as a string literal
+///
+/// **Unstable, internal:** Only meant for debugging and bug reports,
+/// the format may change in any release. Needs the `internals` feature.
+/// ```ignore
+/// println!("{}", structstruck::debug_type_tree!(Box<struct { value: usize }>));
+/// ```
+#[cfg(feature = "internals")]
+#[proc_macro]
+pub fn debug_type_tree(item: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let mut ret = Default::default();
+    imp::debug_type_tree(item.into(), &mut ret);
+    ret.into()
+}
+
+/// The types generated by [`strike!`], as an array of `(name, source)` string pairs
+///
+/// Meant for tools that want to look at the generated types one by one, e.g. to build a schema registry.
+/// Only type declarations are listed, impls and the like are left out.
+///
+/// **Unstable, internal:** Needs the `internals` feature, the format may change in any release.
+/// ```ignore
+/// const TYPES: &[(&str, &str)] = &structstruck::generated_declarations! {
+///     struct Outer {
+///         inner: struct { value: usize }
+///     }
+/// };
+/// // [("Inner", "struct Inner { value : usize }"), ("Outer", "struct Outer { inner : Inner }")]
+/// ```
+#[cfg(feature = "internals")]
+#[proc_macro]
+pub fn generated_declarations(item: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let mut ret = Default::default();
+    imp::generated_declarations(item.into(), &mut ret);
+    ret.into()
+}
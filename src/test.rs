@@ -1,4 +1,7 @@
-use crate::imp::{recurse_through_definition, type_tree, TypeTree};
+use crate::imp::{
+    debug_type_tree, generated_declarations, preset, recurse_through_children,
+    recurse_through_definition, type_tree, valid_type_name, TypeTree,
+};
 use proc_macro2::{Delimiter, Group, TokenStream, TokenTree};
 use quote::quote;
 
@@ -47,9 +50,9 @@ fn strikethrough_derive() {
         struct Shared {
             d: i32
         }
+        #[gobbel]
         #[striked_attr]
         #[derive(Debug, Default, PartialEq)]
-        #[gobbel]
         struct A {
             b: Shared,
             c: Shared,
@@ -1043,3 +1046,3304 @@ fn strikethrough_deprecated() {
     assert!(out.contains("deprecated"));
     assert!(out.contains("structstruck::each"));
 }
+
+#[test]
+fn name_from_doc() {
+    let from = quote! {
+        #[structstruck::name_from_doc]
+        struct Parent {
+            /// Storage settings
+            data: struct { a: u32 },
+            /// Not a clean phrase, sadly.
+            inner: struct { b: u32 },
+            plain: struct { c: u32 },
+        }
+    };
+    let out = quote! {
+        struct StorageSettings { a: u32 }
+        struct Inner { b: u32 }
+        struct Plain { c: u32 }
+        struct Parent {
+            /// Storage settings
+            data: StorageSettings,
+            /// Not a clean phrase, sadly.
+            inner: Inner,
+            plain: Plain,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn phantom_for_unused_generics() {
+    let from = quote! {
+        struct Outer<'a, T> {
+            inner: struct<'a, T> { x: u32 },
+            tuple: struct<T>(u8),
+            used: struct<'a> { s: &'a str },
+            r: &'a T,
+        }
+    };
+    let out = quote! {
+        struct Inner<'a, T> { x: u32, _phantom: ::core::marker::PhantomData<(&'a (), T,)>, }
+        struct Tuple<T>(u8, ::core::marker::PhantomData<(T,)>,);
+        struct Used<'a> { s: &'a str }
+        struct Outer<'a, T> {
+            inner: Inner<'a, T>,
+            tuple: Tuple<T>,
+            used: Used<'a>,
+            r: &'a T,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn no_phantom_for_top_level() {
+    let from = quote! {
+        struct Outer<T> {
+            inner: struct<U> { x: u32 },
+        }
+    };
+    let out = quote! {
+        struct Inner<U> { x: u32, _phantom: ::core::marker::PhantomData<(U,)>, }
+        struct Outer<T> {
+            inner: Inner<U>,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn no_phantom_data() {
+    let from = quote! {
+        struct Outer {
+            inner: struct<T> {
+                #![structstruck::no_phantom_data]
+                x: u32
+            },
+        }
+    };
+    let out = quote! {
+        struct Inner<T> { x: u32 }
+        struct Outer {
+            inner: Inner<T>,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn short_names_override() {
+    let from = quote! {
+        #[structstruck::each[structstruck::long_names]]
+        struct A {
+            b: struct {
+                #![structstruck::short_names]
+                c: struct {
+                    d: struct {}
+                }
+            }
+        }
+    };
+    let out = quote! {
+        struct CD {}
+        struct C {
+            d: CD
+        }
+        struct AB {
+            c: C
+        }
+        struct A {
+            b: AB
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn numbering_modes() {
+    let from = quote! {
+        #[structstruck::numbering(always)]
+        enum Parent {
+            Tuple(struct { b: u8 }, struct { c: u8 })
+        }
+    };
+    let out = quote! {
+        struct Tuple0 { b: u8 }
+        struct Tuple1 { c: u8 }
+        enum Parent {
+            Tuple(Tuple0, Tuple1),
+        }
+    };
+    check(from, out);
+
+    let from = quote! {
+        #[structstruck::numbering(on_collision)]
+        enum Parent {
+            Tuple(u8, struct { c: u8 }, struct { d: u8 })
+        }
+    };
+    let out = quote! {
+        struct Tuple { c: u8 }
+        struct Tuple2 { d: u8 }
+        enum Parent {
+            Tuple(u8, Tuple, Tuple2),
+        }
+    };
+    check(from, out);
+
+    let from = quote! {
+        #[structstruck::numbering(never)]
+        enum Parent {
+            Tuple(u8, struct { c: u8 })
+        }
+    };
+    let out = quote! {
+        struct Tuple { c: u8 }
+        enum Parent {
+            Tuple(u8, Tuple),
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn numbering_invalid() {
+    let from = quote! {
+        #[structstruck::numbering(sometimes)]
+        struct Parent(struct {});
+    };
+    let mut to = TokenStream::new();
+    recurse_through_definition(from, vec![], false, &mut to);
+    assert!(to.to_string().contains("compile_error"));
+}
+
+#[test]
+fn path_field_anonymous_enum() {
+    let from = quote! {
+        #[structstruck::each[structstruck::long_names]]
+        struct Machine {
+            state: enum {
+                Idle,
+                Running(struct { id: u32 }),
+                Paused { job: struct { id: u32 } },
+            }
+        }
+    };
+    let out = quote! {
+        struct MachineStateRunning { id: u32 }
+        struct MachineStatePausedJob { id: u32 }
+        enum MachineState {
+            Idle,
+            Running(MachineStateRunning),
+            Paused { job: MachineStatePausedJob },
+        }
+        struct Machine {
+            state: MachineState
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn debug_dump() {
+    let from = quote! {
+        #[structstruck::debug]
+        struct Outer {
+            inner: struct { value: usize }
+        }
+    };
+    let mut to = TokenStream::new();
+    recurse_through_definition(from, vec![], false, &mut to);
+    let to = to.to_string();
+    assert!(to.contains("deprecated"));
+    assert!(to.contains("structstruck generated: struct Inner"));
+    assert!(!to.contains("structstruck :: debug"));
+}
+
+#[test]
+fn generic_defaults() {
+    let from = quote! {
+        struct Outer<T = struct { x: u8 }, U = struct Named { y: u8 }> {
+            t: T,
+            u: U,
+        }
+    };
+    let out = quote! {
+        struct OuterT { x: u8 }
+        struct Named { y: u8 }
+        struct Outer<T = OuterT, U = Named> {
+            t: T,
+            u: U,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn generic_defaults_in_impls() {
+    let from = quote! {
+        struct Outer<T: Clone = u8, const N: usize = 4> {
+            #[structstruck::default = [T::default(); N]]
+            cells: [T; N],
+        }
+    };
+    let out = quote! {
+        struct Outer<T: Clone = u8, const N: usize = 4> {
+            cells: [T; N],
+        }
+        #[automatically_derived]
+        #[allow(clippy::all)]
+        impl<T: Clone, const N: usize> ::core::default::Default for Outer<T, N>
+        where
+            T: ::core::default::Default,
+        {
+            fn default() -> Self {
+                Self {
+                    cells: [T::default(); N],
+                }
+            }
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn generic_defaults_invalid() {
+    let from = quote! {
+        struct Outer<T = > {
+            t: T,
+        }
+    };
+    let mut to = TokenStream::new();
+    recurse_through_definition(from, vec![], false, &mut to);
+    assert!(to.to_string().contains("compile_error"));
+}
+
+#[test]
+fn cfg_on_field() {
+    let from = quote! {
+        struct Outer {
+            #[cfg(feature = "x")]
+            inner: struct { deep: struct {} },
+            tuple: struct (#[cfg(test)] struct Tested {}),
+        }
+    };
+    let out = quote! {
+        #[cfg(feature = "x")]
+        struct Deep {}
+        #[cfg(feature = "x")]
+        struct Inner { deep: Deep }
+        #[cfg(test)]
+        struct Tested {}
+        struct Tuple(#[cfg(test)] Tested);
+        struct Outer {
+            #[cfg(feature = "x")]
+            inner: Inner,
+            tuple: Tuple,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn slice_element() {
+    let from = quote! {
+        struct Outer {
+            items: Box<[struct Item { v: u8 }]>,
+            anon: &'static [struct { w: u8 }],
+            arr: [u8; 4],
+        }
+    };
+    let out = quote! {
+        struct Item { v: u8 }
+        struct Anon { w: u8 }
+        struct Outer {
+            items: Box<[Item]>,
+            anon: &'static [Anon],
+            arr: [u8; 4],
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn field_defaults() {
+    let from = quote! {
+        struct Outer {
+            inner: struct {
+                #[structstruck::default = 42]
+                answer: u32,
+                other: String,
+            },
+            tuple: struct<T>(#[structstruck::default = vec![]] Vec<T>, bool),
+        }
+    };
+    let out = quote! {
+        struct Inner {
+            answer: u32,
+            other: String,
+        }
+        #[automatically_derived]
+        #[allow(clippy::all)]
+        impl ::core::default::Default for Inner {
+            fn default() -> Self {
+                Self {
+                    answer: 42,
+                    other: ::core::default::Default::default(),
+                }
+            }
+        }
+        struct Tuple<T>(Vec<T>, bool);
+        #[automatically_derived]
+        #[allow(clippy::all)]
+        impl<T> ::core::default::Default for Tuple<T>
+        where
+            T: ::core::default::Default,
+        {
+            fn default() -> Self {
+                Self(vec![], ::core::default::Default::default(),)
+            }
+        }
+        struct Outer {
+            inner: Inner,
+            tuple: Tuple<T>,
+        }
+    };
+    check(from, out);
+
+    let from = quote! {
+        #[structstruck::each[derive(Debug, Default)]]
+        struct Outer {
+            inner: struct {
+                #[structstruck::default = 42]
+                answer: u32,
+            },
+        }
+    };
+    let out = quote! {
+        #[derive(Debug,)]
+        struct Inner {
+            answer: u32,
+        }
+        #[automatically_derived]
+        #[allow(clippy::all)]
+        impl ::core::default::Default for Inner {
+            fn default() -> Self {
+                Self {
+                    answer: 42,
+                }
+            }
+        }
+        #[derive(Debug, Default)]
+        struct Outer {
+            inner: Inner,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn attribute_order() {
+    let from = quote! {
+        #[structstruck::each[derive(Serialize)]]
+        #[serde_as]
+        struct Parent {
+            a: #[serde_as] struct {
+                #[serde_as(as = "DisplayFromStr")]
+                b: u32,
+            },
+        }
+    };
+    let out = quote! {
+        #[serde_as]
+        #[derive(Serialize)]
+        struct A {
+            #[serde_as(as = "DisplayFromStr")]
+            b: u32,
+        }
+        #[derive(Serialize)]
+        #[serde_as]
+        struct Parent {
+            a: A,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn struct_in_block_stays() {
+    let from = quote! {
+        struct Outer {
+            generic: Foo<{ struct Local; 3 }>,
+            array: [u8; { struct Local; 3 }],
+        }
+    };
+    let out = quote! {
+        struct Outer {
+            generic: Foo<{ struct Local; 3 }>,
+            array: [u8; { struct Local; 3 }],
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn explicit_vis_attr() {
+    let from = quote! {
+        struct Outer {
+            pub a: struct {
+                #![structstruck::vis(pub(crate))]
+                x: u8
+            },
+            b: pub struct {
+                #![structstruck::vis()]
+                y: u8
+            },
+        }
+    };
+    let out = quote! {
+        pub(crate) struct A { x: u8 }
+        struct B { y: u8 }
+        struct Outer {
+            pub a: A,
+            b: B,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn explicit_vis_attr_invalid() {
+    let from = quote! {
+        #[structstruck::vis(public)]
+        struct Outer {}
+    };
+    let mut to = TokenStream::new();
+    recurse_through_definition(from, vec![], false, &mut to);
+    assert!(to.to_string().contains("compile_error"));
+}
+
+#[test]
+fn serde_rename_all_placement() {
+    let from = quote! {
+        #[structstruck::each[derive(Serialize)]]
+        #[structstruck::each[serde(rename_all = "camelCase")]]
+        struct Outer {
+            some_struct: struct {
+                inner_field: u8,
+            },
+            some_enum: enum {
+                FirstVariant { variant_field: u8 },
+            },
+            some_alias: type = u32,
+        }
+    };
+    let out = quote! {
+        #[derive(Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct SomeStruct {
+            inner_field: u8,
+        }
+        #[derive(Serialize)]
+        #[serde(rename_all = "camelCase")]
+        enum SomeEnum {
+            FirstVariant { variant_field: u8 },
+        }
+        type SomeAlias = u32;
+        #[derive(Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Outer {
+            some_struct: SomeStruct,
+            some_enum: SomeEnum,
+            some_alias: SomeAlias,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn serde_cfg_attr_on_alias() {
+    let from = quote! {
+        #[structstruck::each[cfg_attr(feature = "serde", derive(Serialize), serde(rename_all = "camelCase"), allow(dead_code))]]
+        #[structstruck::each[cfg_attr(feature = "serde", serde(deny_unknown_fields))]]
+        struct Outer {
+            some_alias: type = u32,
+        }
+    };
+    let out = quote! {
+        #[cfg_attr(feature = "serde", allow(dead_code))]
+        type SomeAlias = u32;
+        #[cfg_attr(feature = "serde", derive(Serialize), serde(rename_all = "camelCase"), allow(dead_code))]
+        #[cfg_attr(feature = "serde", serde(deny_unknown_fields))]
+        struct Outer {
+            some_alias: SomeAlias,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn derive_on_alias() {
+    let from = quote! {
+        struct Outer {
+            some_alias: #[derive(Clone)] type = u32,
+        }
+    };
+    let mut to = TokenStream::new();
+    recurse_through_definition(from, vec![], false, &mut to);
+    assert!(to.to_string().contains("compile_error"));
+    assert!(!to.to_string().contains("# [derive"));
+}
+
+#[test]
+fn each_parens_trailing_comma() {
+    let from = quote! {
+        #[structstruck::each(derive(Clone),)]
+        struct Parent {
+            a: struct {},
+        }
+    };
+    let out = quote! {
+        #[derive(Clone)]
+        struct A {}
+        #[derive(Clone)]
+        struct Parent {
+            a: A,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn each_braces() {
+    let from = quote! {
+        #[structstruck::each{derive(Clone)}]
+        struct Parent {}
+    };
+    let mut to = TokenStream::new();
+    recurse_through_definition(from, vec![], false, &mut to);
+    let to = to.to_string();
+    assert!(to.contains("compile_error"));
+    assert!(to.contains("found {group}"));
+}
+
+#[test]
+fn associated_type_binding() {
+    let from = quote! {
+        #[structstruck::long_names]
+        type Rows = impl Iterator<Item = struct { id: u32 }>;
+    };
+    let out = quote! {
+        struct RowsItem { id: u32 }
+        type Rows = impl Iterator<Item = RowsItem>;
+    };
+    check(from, out);
+
+    let from = quote! {
+        struct Outer {
+            iter: Box<dyn Iterator<Item = struct Row { id: u32 }>>,
+        }
+    };
+    let out = quote! {
+        struct Row { id: u32 }
+        struct Outer {
+            iter: Box<dyn Iterator<Item = Row>>,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn children_only() {
+    let from = quote! {
+        #[structstruck::each[derive(Debug)]]
+        struct Outer {
+            inner: struct {
+                deeper: struct {},
+            },
+        }
+    };
+    let out = quote! {
+        #[derive(Debug)]
+        struct Deeper {}
+        #[derive(Debug)]
+        struct Inner {
+            deeper: Deeper,
+        }
+    };
+    let mut plan = TokenStream::new();
+    recurse_through_children(from, &mut plan);
+    assert_eq!(pretty(plan), pretty(out));
+}
+
+#[test]
+fn several_anonymous_in_generic() {
+    let from = quote! {
+        struct Outer {
+            res: Result<struct { a: u8 }, enum { B }>,
+            named: Result<struct Good {}, struct {}>,
+        }
+    };
+    let out = quote! {
+        struct Res { a: u8 }
+        enum Res1 { B }
+        struct Good {}
+        struct Named {}
+        struct Outer {
+            res: Result<Res, Res1>,
+            named: Result<Good, Named>,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn self_referential() {
+    let from = quote! {
+        struct Tree {
+            children: Vec<struct Node<T> {
+                value: T,
+                children: Vec<Box<Self>>,
+            }>,
+            parent: Option<Box<Self>>,
+        }
+    };
+    let out = quote! {
+        struct Node<T> {
+            value: T,
+            children: Vec<Box<Self>>,
+        }
+        struct Tree {
+            children: Vec<Node<T> >,
+            parent: Option<Box<Self>>,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn each_field() {
+    let from = quote! {
+        #[structstruck::each_field(pub id: u64)]
+        struct Outer {
+            inner: struct { value: u8 },
+            kind: enum { A },
+            skipped: struct {
+                #![structstruck::no_each_field]
+                deeper: struct {},
+            },
+        }
+    };
+    let out = quote! {
+        struct Inner { value: u8, pub id: u64, }
+        enum Kind { A }
+        struct Deeper {}
+        struct Skipped { deeper: Deeper, }
+        struct Outer {
+            inner: Inner,
+            kind: Kind,
+            skipped: Skipped,
+            pub id: u64,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn each_field_collision() {
+    let from = quote! {
+        #[structstruck::each_field(id: u64)]
+        struct Outer {
+            id: u32,
+        }
+    };
+    let mut to = TokenStream::new();
+    recurse_through_definition(from, vec![], false, &mut to);
+    assert!(to.to_string().contains("compile_error"));
+}
+
+#[test]
+fn where_clause_bounds() {
+    let from = quote! {
+        struct Outer<T, U>
+        where
+            T: Into<struct { x: u8 }> + Clone,
+            U: ::std::fmt::Debug,
+        {
+            t: T,
+            u: U,
+        }
+    };
+    let out = quote! {
+        struct OuterT { x: u8 }
+        struct Outer<T, U>
+        where
+            T: Into<OuterT> + Clone,
+            U: ::std::fmt::Debug,
+        {
+            t: T,
+            u: U,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn doc_comment_before_anonymous() {
+    let from = quote! {
+        struct Outer {
+            /// On the field
+            field: /// On the type
+            struct {
+                x: u8,
+            },
+        }
+    };
+    let out = quote! {
+        /// On the type
+        struct Field {
+            x: u8,
+        }
+        struct Outer {
+            /// On the field
+            field: Field,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn const_and_static_items() {
+    let from = quote! {
+        pub const DEFAULT: struct Config { a: u8 } = Config { a: 1 };
+    };
+    let out = quote! {
+        pub struct Config { a: u8 }
+        pub const DEFAULT: Config = Config { a: 1 };
+    };
+    check(from, out);
+
+    let from = quote! {
+        static mut COUNTER: Option<struct { n: u32 }> = None;
+    };
+    let out = quote! {
+        struct Counter { n: u32 }
+        static mut COUNTER: Option<Counter> = None;
+    };
+    check(from, out);
+}
+
+#[test]
+fn type_tree_comparisons() {
+    for inp in [
+        quote!(1 < 2),
+        quote!((N) < M),
+        quote!(N <= M),
+        quote!((N) < M as usize),
+    ] {
+        let inp = inp.into_iter().collect::<Vec<_>>();
+        let mut out = proc_macro2::TokenStream::new();
+        let res = type_tree(&inp, &mut out);
+        assert!(out.is_empty(), "{}", out);
+        assert!(res.iter().all(|t| matches!(t, TypeTree::Token(_))));
+    }
+    // Still generics
+    let inp = quote!(<T as Trait>::Assoc).into_iter().collect::<Vec<_>>();
+    let mut out = proc_macro2::TokenStream::new();
+    let res = type_tree(&inp, &mut out);
+    assert!(out.is_empty(), "{}", out);
+    assert!(matches!(res[0], TypeTree::Group(..)));
+    let inp = quote!(<<T as A>::B as C>::D)
+        .into_iter()
+        .collect::<Vec<_>>();
+    let mut out = proc_macro2::TokenStream::new();
+    let res = type_tree(&inp, &mut out);
+    assert!(out.is_empty(), "{}", out);
+    assert!(
+        matches!(&res[0], TypeTree::Group(_, inner, _) if matches!(inner[0], TypeTree::Group(..)))
+    );
+}
+
+#[test]
+fn unclosed_generics() {
+    let from = quote! {
+        struct Outer {
+            list: Vec<struct { x: u8 },
+        }
+    };
+    let mut out = TokenStream::new();
+    recurse_through_definition(from, vec![], false, &mut out);
+    let out = out.to_string();
+    assert!(out.contains("Unclosed group"), "{}", out);
+    assert!(!out.contains("Unsupported declaration"), "{}", out);
+}
+
+#[test]
+fn const_generic_comparisons() {
+    let from = quote! {
+        struct Outer {
+            arr: [u8; { (N < M) as usize }],
+            generic: Foo<{ 1 < 2 }, struct { x: u8 }>,
+        }
+    };
+    let out = quote! {
+        struct Generic { x: u8 }
+        struct Outer {
+            arr: [u8; { (N < M) as usize }],
+            generic: Foo<{ 1 < 2 }, Generic>,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn tuple_accessors() {
+    let from = quote! {
+        #[structstruck::each[structstruck::accessors]]
+        pub struct Meters(pub struct Inner(f64));
+    };
+    let out = quote! {
+        pub struct Inner(f64);
+        #[automatically_derived]
+        #[allow(dead_code)]
+        #[allow(clippy::all)]
+        impl Inner {
+            pub fn value(&self) -> &f64 {
+                &self.0
+            }
+        }
+        pub struct Meters(Inner);
+        #[automatically_derived]
+        #[allow(dead_code)]
+        #[allow(clippy::all)]
+        impl Meters {
+            pub fn inner(&self) -> &Inner {
+                &self.0
+            }
+        }
+    };
+    check(from, out);
+
+    let from = quote! {
+        #[structstruck::accessors]
+        struct Meters(pub struct Type(f64));
+    };
+    let out = quote! {
+        pub struct Type(f64);
+        struct Meters(Type);
+        #[automatically_derived]
+        #[allow(dead_code)]
+        #[allow(clippy::all)]
+        impl Meters {
+            fn r#type(&self) -> &Type {
+                &self.0
+            }
+        }
+    };
+    check(from, out);
+
+    let from = quote! {
+        #[structstruck::accessors]
+        struct Step(struct r#Move(u8));
+    };
+    let out = quote! {
+        struct r#Move(u8);
+        struct Step(r#Move);
+        #[automatically_derived]
+        #[allow(dead_code)]
+        #[allow(clippy::all)]
+        impl Step {
+            fn r#move(&self) -> &r#Move {
+                &self.0
+            }
+        }
+    };
+    check(from, out);
+
+    let from = quote! {
+        #[structstruck::accessors]
+        struct Host(struct HTTPServer(u16));
+    };
+    let out = quote! {
+        struct HTTPServer(u16);
+        struct Host(HTTPServer);
+        #[automatically_derived]
+        #[allow(dead_code)]
+        #[allow(clippy::all)]
+        impl Host {
+            fn http_server(&self) -> &HTTPServer {
+                &self.0
+            }
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn generics_through_nesting() {
+    let from = quote! {
+        struct A<T: Clone, U> {
+            b: struct {
+                c: struct {
+                    t: T,
+                },
+                flag: bool,
+            },
+            u: U,
+            plain: struct { x: u8 },
+        }
+    };
+    let out = quote! {
+        struct C<T: Clone> { t: T, }
+        struct B<T: Clone> { c: C<T>, flag: bool, }
+        struct Plain { x: u8 }
+        struct A<T: Clone, U> {
+            b: B<T>,
+            u: U,
+            plain: Plain,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn reexport_module() {
+    let from = quote! {
+        #[structstruck::reexport(types)]
+        pub struct Outer {
+            pub inner: struct { value: usize },
+            krate: struct { #![structstruck::vis(pub(crate))] value: usize },
+            hidden: struct { value: usize },
+        }
+    };
+    let out = quote! {
+        pub struct Inner { value: usize }
+        pub(crate) struct Krate { value: usize }
+        struct Hidden { value: usize }
+        pub struct Outer {
+            pub inner: Inner,
+            krate: Krate,
+            hidden: Hidden,
+        }
+        pub mod types {
+            pub use super::Inner;
+            pub(crate) use super::Krate;
+            pub use super::Outer;
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn reexport_path() {
+    let from = quote! {
+        #[structstruck::reexport(crate::shapes::types)]
+        pub struct Outer {
+            pub inner: struct { value: usize },
+        }
+    };
+    let out = quote! {
+        pub struct Inner { value: usize }
+        pub struct Outer {
+            pub inner: Inner,
+        }
+        pub mod types {
+            pub use crate::shapes::Inner;
+            pub use crate::shapes::Outer;
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn reexport_cfg() {
+    let from = quote! {
+        #[structstruck::reexport(types)]
+        pub struct Outer {
+            #[cfg(feature = "inner")]
+            pub inner: struct { value: usize },
+        }
+    };
+    let out = quote! {
+        #[cfg(feature = "inner")]
+        pub struct Inner { value: usize }
+        pub struct Outer {
+            #[cfg(feature = "inner")]
+            pub inner: Inner,
+        }
+        pub mod types {
+            #[cfg(feature = "inner")]
+            pub use super::Inner;
+            pub use super::Outer;
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn reexport_invalid() {
+    let from = quote! {
+        #[structstruck::reexport("types")]
+        struct Outer {}
+    };
+    let mut out = TokenStream::new();
+    recurse_through_definition(from, vec![], false, &mut out);
+    assert!(out.to_string().contains("compile_error"));
+    for from in [
+        quote!(
+            #[structstruck::reexport(crate::)]
+            struct Outer {}
+        ),
+        quote!(
+            #[structstruck::reexport(crate:types)]
+            struct Outer {}
+        ),
+    ] {
+        let mut out = TokenStream::new();
+        recurse_through_definition(from, vec![], false, &mut out);
+        assert!(out.to_string().contains("compile_error"));
+    }
+}
+
+#[test]
+fn enum_discriminants() {
+    let from = quote! {
+        #[repr(u8)]
+        enum E {
+            A = 0,
+            B = 4,
+            C(struct { x: u8 }) = 8,
+        }
+    };
+    let out = quote! {
+        struct C { x: u8 }
+        #[repr(u8)]
+        enum E {
+            A = 0,
+            B = 4,
+            C(C) = 8,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn emit_after() {
+    let from = quote! {
+        #[structstruck::emit(after)]
+        struct Outer {
+            a: struct { x: u8 },
+            b: struct {
+                #![structstruck::emit(before)]
+                c: struct { y: u8 },
+            },
+        }
+    };
+    let out = quote! {
+        struct Outer {
+            a: A,
+            b: B,
+        }
+        struct A { x: u8 }
+        struct C { y: u8 }
+        struct B { c: C, }
+    };
+    check(from, out);
+}
+
+#[test]
+fn emit_invalid() {
+    let from = quote! {
+        #[structstruck::emit(sideways)]
+        struct Outer {}
+    };
+    let mut out = TokenStream::new();
+    recurse_through_definition(from, vec![], false, &mut out);
+    assert!(out.to_string().contains("compile_error"));
+}
+
+#[test]
+fn inherited_bounds() {
+    let from = quote! {
+        struct Outer<'a, 'b: 'a, T: Into<U> + ?Sized, U> {
+            inner: struct { t: &'b T },
+            u: &'a U,
+        }
+    };
+    let out = quote! {
+        struct Inner<'a, 'b: 'a, T: Into<U> + ?Sized, U> {
+            t: &'b T,
+            _phantom: ::core::marker::PhantomData<(&'a (), U,)>,
+        }
+        struct Outer<'a, 'b: 'a, T: Into<U> + ?Sized, U> {
+            inner: Inner<'a, 'b, T, U>,
+            u: &'a U,
+        }
+    };
+    check(from, out);
+
+    // No PhantomData for enums and unions, so their bounds are cut down to the parameters they use
+    let from = quote! {
+        struct Outer<'a, 'b: 'a, T: Into<U> + ?Sized, U> {
+            kind: enum { Borrowed(&'b T) },
+            raw: union { bits: &'b u8 },
+            u: &'a U,
+        }
+    };
+    let out = quote! {
+        enum Kind<'b, T: ?Sized> { Borrowed(&'b T) }
+        union Raw<'b> { bits: &'b u8 }
+        struct Outer<'a, 'b: 'a, T: Into<U> + ?Sized, U> {
+            kind: Kind<'b, T>,
+            raw: Raw<'b>,
+            u: &'a U,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn dyn_trait_arguments() {
+    let from = quote! {
+        struct Outer {
+            handler: Box<dyn Handler<struct Ctx { id: u32 }> + Send>,
+            callback: Box<dyn Fn(u8) -> u8>,
+            anon: &'static dyn Handler<struct { id: u32 }>,
+        }
+    };
+    let out = quote! {
+        struct Ctx { id: u32 }
+        struct Anon { id: u32 }
+        struct Outer {
+            handler: Box<dyn Handler<Ctx> + Send>,
+            callback: Box<dyn Fn(u8) -> u8>,
+            anon: &'static dyn Handler<Anon>,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn max_types() {
+    let from = quote! {
+        #[structstruck::max_types(3)]
+        struct Outer {
+            a: struct {},
+            b: struct {},
+        }
+    };
+    let mut out = TokenStream::new();
+    recurse_through_definition(from, vec![], false, &mut out);
+    assert!(!out.to_string().contains("compile_error"));
+
+    let from = quote! {
+        #[structstruck::max_types(2)]
+        struct Outer {
+            a: struct {},
+            b: struct {},
+        }
+    };
+    let mut out = TokenStream::new();
+    recurse_through_definition(from, vec![], false, &mut out);
+    let out = out.to_string();
+    assert!(out.contains("compile_error"));
+    assert!(out.contains("limit exceeded at Outer"));
+    assert!(!out.contains("struct A"));
+}
+
+#[test]
+fn numeric_name_hints() {
+    assert_eq!(valid_type_name("0".into()), "Field0");
+    assert_eq!(valid_type_name("".into()), "Field");
+    assert_eq!(valid_type_name("2fa".into()), "Field2fa");
+    assert_eq!(valid_type_name("Internal".into()), "Internal");
+
+    let from = quote! {
+        #[structstruck::name_from_doc]
+        struct Outer {
+            _0: struct { x: u8 },
+            _internal: struct { y: u8 },
+            /// 2fa settings
+            data: struct { z: u8 },
+        }
+    };
+    let out = quote! {
+        struct Field0 { x: u8 }
+        struct Internal { y: u8 }
+        struct Field2faSettings { z: u8 }
+        struct Outer {
+            _0: Field0,
+            _internal: Internal,
+            /// 2fa settings
+            data: Field2faSettings,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn each_depth_and_kind() {
+    let from = quote! {
+        #[structstruck::each(depth = 1)[derive(A)]]
+        #[structstruck::each[derive(B)]]
+        #[structstruck::each(enum, depth = 2)[derive(C)]]
+        struct Outer {
+            inner: struct {
+                innermost: struct {},
+                kind: enum { X },
+            },
+            deep: enum { Y(struct { z: enum { Z } }) },
+        }
+    };
+    let out = quote! {
+        #[derive(B)]
+        struct Innermost {}
+        #[derive(B)]
+        #[derive(C)]
+        enum Kind { X }
+        #[derive(A)]
+        #[derive(B)]
+        struct Inner {
+            innermost: Innermost,
+            kind: Kind,
+        }
+        #[derive(B)]
+        enum Z { Z }
+        #[derive(B)]
+        struct Y { z: Z, }
+        #[derive(A)]
+        #[derive(B)]
+        #[derive(C)]
+        enum Deep { Y(Y) }
+        #[derive(A)]
+        #[derive(B)]
+        struct Outer {
+            inner: Inner,
+            deep: Deep,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn each_filter_invalid() {
+    let from = quote! {
+        #[structstruck::each(depth = deep)[derive(A)]]
+        struct Outer {}
+    };
+    let mut out = TokenStream::new();
+    recurse_through_definition(from, vec![], false, &mut out);
+    assert!(out.to_string().contains("compile_error"));
+}
+
+#[test]
+fn unit_struct_in_field() {
+    let from = quote! {
+        struct Outer {
+            marker: struct Marker,
+            wrapped: Option<struct Wrapped>,
+        }
+    };
+    let out = quote! {
+        struct Marker;
+        struct Wrapped;
+        struct Outer {
+            marker: Marker,
+            wrapped: Option<Wrapped>,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn declaration_without_body() {
+    for from in [
+        quote! { struct Outer { bare: struct } },
+        quote! { struct Outer { e: enum E } },
+        quote! { struct Outer { u: Option<union U> } },
+    ] {
+        let mut out = TokenStream::new();
+        recurse_through_definition(from, vec![], false, &mut out);
+        assert!(out.to_string().contains("without a body"));
+    }
+}
+
+#[test]
+fn mixed_variant_shapes() {
+    let from = quote! {
+        enum Msg {
+            Ping,
+            Data { payload: struct { len: usize } },
+            Code(enum { Ok, Err }),
+            Named { inner: struct P { x: u8 } },
+        }
+    };
+    let out = quote! {
+        struct Payload { len: usize }
+        enum Code { Ok, Err }
+        struct P { x: u8 }
+        enum Msg {
+            Ping,
+            Data { payload: Payload },
+            Code(Code),
+            Named { inner: P },
+        }
+    };
+    check(from, out);
+
+    let from = quote! {
+        #[structstruck::long_names]
+        enum Msg {
+            Ping,
+            Data { payload: struct { len: usize } },
+            Code(enum { Ok, Err }),
+        }
+    };
+    let out = quote! {
+        struct MsgDataPayload { len: usize }
+        enum MsgCode { Ok, Err }
+        enum Msg {
+            Ping,
+            Data { payload: MsgDataPayload },
+            Code(MsgCode),
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn name_affixes() {
+    let from = quote! {
+        #[structstruck::name_prefix("My")]
+        #[structstruck::name_suffix("Dto")]
+        struct Outer {
+            inner: struct {
+                deeper: struct {},
+                named: struct Named {},
+            },
+            other: struct {
+                #![structstruck::name_suffix("")]
+                plain: struct {},
+            },
+        }
+    };
+    let out = quote! {
+        struct MyDeeperDto {}
+        struct Named {}
+        struct MyInnerDto {
+            deeper: MyDeeperDto,
+            named: Named,
+        }
+        struct MyPlain {}
+        struct MyOtherDto {
+            plain: MyPlain,
+        }
+        struct Outer {
+            inner: MyInnerDto,
+            other: MyOtherDto,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn closing_generics_in_a_run() {
+    // proc_macro2 splits >>> into three joint > puncts, no need to split them ourselves
+    let from = quote! {
+        struct Outer {
+            deep: Vec<Vec<Option<struct X { x: u8 }>>>,
+            deeper: Box<Vec<Vec<Option<struct { y: u8 }>>>>,
+        }
+    };
+    let out = quote! {
+        struct X { x: u8 }
+        struct Deeper { y: u8 }
+        struct Outer {
+            deep: Vec<Vec<Option<X> > >,
+            deeper: Box<Vec<Vec<Option<Deeper> > > >,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn fn_qualifiers() {
+    let from = quote! {
+        #[structstruck::each[derive(Debug)]]
+        pub async fn load(id: u32) -> struct { bytes: Vec<u8> } {
+            todo!()
+        }
+    };
+    let out = quote! {
+        #[derive(Debug)]
+        pub struct Load { bytes: Vec<u8> }
+        pub async fn load(id: u32) -> Load {
+            todo!()
+        }
+    };
+    check(from, out);
+
+    let from = quote! {
+        unsafe fn poke(target: struct Target { addr: usize }, value: Option<struct { v: u8 }>) {}
+    };
+    let out = quote! {
+        struct Target { addr: usize }
+        struct Value { v: u8 }
+        unsafe fn poke(target: Target, value: Option<Value>) {}
+    };
+    check(from, out);
+
+    let from = quote! {
+        extern "C" fn callback<T>(ctx: struct { data: *mut T }) {}
+    };
+    let out = quote! {
+        struct Ctx<T> { data: *mut T }
+        extern "C" fn callback<T>(ctx: Ctx<T>) {}
+    };
+    check(from, out);
+
+    let from = quote! {
+        const fn wrap<T>(t: T) -> struct Wrapped<T> { t: T } where T: Copy {
+            Wrapped { t }
+        }
+    };
+    let out = quote! {
+        struct Wrapped<T> { t: T }
+        const fn wrap<T>(t: T) -> Wrapped<T> where T: Copy {
+            Wrapped { t }
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn private_in_pub_enum() {
+    let from = quote! {
+        pub enum Outer {
+            Open(struct { x: u8 }),
+            Hidden(#[structstruck::private] struct { y: u8 }),
+            Named {
+                #[structstruck::private]
+                z: struct { z: u8 },
+                direct: #[structstruck::private] struct { w: u8 },
+            },
+        }
+    };
+    let out = quote! {
+        pub struct Open { x: u8 }
+        struct Hidden { y: u8 }
+        struct Z { z: u8 }
+        struct Direct { w: u8 }
+        pub enum Outer {
+            Open(Open),
+            Hidden(Hidden),
+            Named {
+                z: Z,
+                direct: Direct,
+            },
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn closure_types() {
+    let from = quote! {
+        struct Outer {
+            named: Box<dyn Fn(struct Arg { a: u8 }) -> struct Ret { r: u8 }>,
+            handler: Box<dyn FnMut(struct { a: u8 }, u16) -> struct { r: u8 }>,
+            pointer: fn(u8) -> u8,
+            tuple: (struct { t: u8 }, u8),
+        }
+    };
+    let out = quote! {
+        struct Arg { a: u8 }
+        struct Ret { r: u8 }
+        struct Handler { a: u8 }
+        struct HandlerOutput { r: u8 }
+        struct Tuple { t: u8 }
+        struct Outer {
+            named: Box<dyn Fn(Arg) -> Ret>,
+            handler: Box<dyn FnMut(Handler, u16) -> HandlerOutput>,
+            pointer: fn(u8) -> u8,
+            tuple: (Tuple, u8),
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn closure_type_in_alias() {
+    let from = quote! {
+        type Callback = Box<dyn Fn(struct Event { id: u32 }) -> struct Outcome { ok: bool }>;
+    };
+    let out = quote! {
+        struct Event { id: u32 }
+        struct Outcome { ok: bool }
+        type Callback = Box<dyn Fn(Event) -> Outcome>;
+    };
+    check(from, out);
+}
+
+#[test]
+fn sibling_references() {
+    // Generated types are siblings in the same scope, so they can be used before and after their declaration
+    let from = quote! {
+        #[structstruck::emit(after)]
+        #[structstruck::reexport(types)]
+        pub struct Outer {
+            pub first: Option<Box<Second>>,
+            pub second: struct { back: Option<Box<First>> },
+            pub third: Vec<Second>,
+            pub first_again: struct First { x: u8 },
+        }
+    };
+    let out = quote! {
+        pub struct Outer {
+            pub first: Option<Box<Second>>,
+            pub second: Second,
+            pub third: Vec<Second>,
+            pub first_again: First,
+        }
+        pub struct Second { back: Option<Box<First>> }
+        pub struct First { x: u8 }
+        pub mod types {
+            pub use super::Outer;
+            pub use super::Second;
+            pub use super::First;
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn derive_here() {
+    let from = quote! {
+        #[structstruck::each[derive(Debug)]]
+        #[derive(Clone)]
+        #[structstruck::derive_here(Clone, PartialEq)]
+        struct Outer {
+            inner: struct {
+                #![structstruck::derive_here(Debug, Hash, Hash)]
+                deeper: struct {},
+            },
+            plain: #[structstruck::derive_here(Debug)] struct {},
+        }
+    };
+    let out = quote! {
+        #[derive(Debug)]
+        struct Deeper {}
+        #[derive(Hash,)]
+        #[derive(Debug)]
+        struct Inner {
+            deeper: Deeper,
+        }
+        #[derive(Debug)]
+        struct Plain {}
+        #[derive(Debug)]
+        #[derive(Clone)]
+        #[derive(PartialEq,)]
+        struct Outer {
+            inner: Inner,
+            plain: Plain,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn const_block_argument() {
+    let from = quote! {
+        struct Outer {
+            field: Matrix<struct Cell { v: u8 }, { struct Tmp; 3 * 3 }>,
+            anon: Matrix<struct { v: u8 }, { 3 * 3 }>,
+        }
+    };
+    let out = quote! {
+        struct Cell { v: u8 }
+        struct Anon { v: u8 }
+        struct Outer {
+            field: Matrix<Cell, { struct Tmp; 3 * 3 }>,
+            anon: Matrix<Anon, { 3 * 3 }>,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn variant_conversions() {
+    let from = quote! {
+        #[structstruck::variant_conversions]
+        enum Msg<T> {
+            Data(struct { t: T }),
+            Boxed(Box<struct { x: u8 }>),
+            Empty,
+        }
+    };
+    let out = quote! {
+        struct Data<T> { t: T }
+        struct Boxed { x: u8 }
+        enum Msg<T> {
+            Data(Data<T>),
+            Boxed(Box<Boxed>),
+            Empty,
+        }
+        #[automatically_derived]
+        #[allow(clippy::all)]
+        impl<T> ::core::convert::From<Data<T> > for Msg<T> {
+            fn from(value: Data<T>) -> Self {
+                Self::Data(value)
+            }
+        }
+        #[automatically_derived]
+        #[allow(clippy::all)]
+        impl<T> ::core::convert::TryFrom<Msg<T> > for Data<T> {
+            type Error = Msg<T>;
+            #[allow(unreachable_patterns)]
+            fn try_from(value: Msg<T>) -> ::core::result::Result<Self, Self::Error> {
+                match value {
+                    Msg::Data(inner) => ::core::result::Result::Ok(inner),
+                    other => ::core::result::Result::Err(other),
+                }
+            }
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn preset_macro() {
+    let from = quote! {
+        strike_debug,
+        #[structstruck::each[derive(Debug)]]
+        #[structstruck::long_names]
+    };
+    let mut out = TokenStream::new();
+    preset(from, &mut out);
+    let expected = quote! {
+        macro_rules! strike_debug {
+            ($($t:tt)*) => {
+                ::structstruck::strike! {
+                    #[structstruck::each[derive(Debug)]]
+                    #[structstruck::long_names]
+                    $($t)*
+                }
+            };
+        }
+    };
+    assert_eq!(out.to_string(), expected.to_string());
+
+    let mut out = TokenStream::new();
+    preset(quote!(strike_debug, derive(Debug)), &mut out);
+    assert!(out.to_string().contains("compile_error"));
+}
+
+#[test]
+fn unsupported_item_kinds() {
+    let cases = [
+        (quote! { extern "C" { fn f(x: u8); } }, "extern block"),
+        (quote! { trait Foo {} }, "Unsupported declaration: trait"),
+        (quote! { pub mod m {} }, "Unsupported declaration: mod"),
+    ];
+    for (from, expected) in cases {
+        let mut out = TokenStream::new();
+        recurse_through_definition(from, vec![], false, &mut out);
+        assert!(out.to_string().contains(expected), "{}", out);
+    }
+}
+
+#[test]
+fn named_in_both_generic_positions() {
+    let from = quote! {
+        #[structstruck::each[structstruck::long_names]]
+        #[structstruck::name_prefix("Gen")]
+        struct Outer {
+            res: Result<struct Success { ok: u8 }, struct Failure { err: u8 }>,
+            mixed: Result<struct { ok: u8 }, struct Failure2 { err: u8 }>,
+        }
+    };
+    let out = quote! {
+        struct Success { ok: u8 }
+        struct Failure { err: u8 }
+        struct GenOuterMixed { ok: u8 }
+        struct Failure2 { err: u8 }
+        struct Outer {
+            res: Result<Success, Failure>,
+            mixed: Result<GenOuterMixed, Failure2>,
+        }
+    };
+    check(from, out);
+}
+
+/// The messages of the compile_error!s in the output
+fn error_messages(out: TokenStream) -> Vec<String> {
+    let tokens = out.into_iter().collect::<Vec<_>>();
+    tokens
+        .windows(3)
+        .filter_map(|w| match w {
+            [TokenTree::Ident(id), TokenTree::Punct(bang), TokenTree::Group(args)]
+                if id == "compile_error" && bang.as_char() == '!' =>
+            {
+                Some(args.stream().to_string())
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn all_errors_reported() {
+    let mut out = TokenStream::new();
+    recurse_through_definition(
+        quote! { struct Outer { inner: struct { x: u8 } } },
+        vec![],
+        false,
+        &mut out,
+    );
+    assert!(error_messages(out).is_empty());
+
+    let mut out = TokenStream::new();
+    recurse_through_definition(
+        quote! {
+            #[structstruck::numbering(sometimes)]
+            struct Outer { bare: struct, e: enum E }
+        },
+        vec![],
+        false,
+        &mut out,
+    );
+    let messages = error_messages(out);
+    assert_eq!(messages.len(), 3, "{:?}", messages);
+    assert!(messages[0].contains("unknown mode"));
+    assert!(messages[1].contains("struct declaration without a body"));
+    assert!(messages[2].contains("enum declaration without a body"));
+}
+
+#[test]
+fn phantom_tags() {
+    let from = quote! {
+        struct Outer {
+            empty: PhantomData<struct Empty {}>,
+            unit: PhantomData<struct Unit;>,
+            bare: PhantomData<struct Bare>,
+        }
+    };
+    let out = quote! {
+        struct Empty {}
+        struct Unit;
+        struct Bare;
+        struct Outer {
+            empty: PhantomData<Empty>,
+            unit: PhantomData<Unit>,
+            bare: PhantomData<Bare>,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn number_separator() {
+    let from = quote! {
+        #[structstruck::number_separator("_")]
+        enum Parent {
+            Tuple(struct { a: u8 }, struct { b: u8 }, struct { c: u8 })
+        }
+    };
+    let out = quote! {
+        struct Tuple { a: u8 }
+        struct Tuple_1 { b: u8 }
+        struct Tuple_2 { c: u8 }
+        enum Parent {
+            Tuple(Tuple, Tuple_1, Tuple_2)
+        }
+    };
+    check(from, out);
+
+    let from = quote! {
+        #[structstruck::long_names]
+        #[structstruck::number_separator("N")]
+        #[structstruck::numbering(always)]
+        struct Parent(struct { a: u8 }, struct { b: u8 }, struct { c: u8 });
+    };
+    let out = quote! {
+        struct ParentN0 { a: u8 }
+        struct ParentN1 { b: u8 }
+        struct ParentN2 { c: u8 }
+        struct Parent(ParentN0, ParentN1, ParentN2);
+    };
+    check(from, out);
+
+    // Also for positions in generic arguments, where the number goes before the suffix
+    let from = quote! {
+        #[structstruck::number_separator("_")]
+        #[structstruck::name_suffix("Data")]
+        struct Parent {
+            pair: Result<struct { ok: u8 }, struct { err: u8 }>,
+        }
+    };
+    let out = quote! {
+        struct PairData { ok: u8 }
+        struct Pair_1Data { err: u8 }
+        struct Parent {
+            pair: Result<PairData, Pair_1Data>,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn derive_for() {
+    let from = quote! {
+        #[structstruck::derive_for(Inner => Clone, Debug)]
+        #[structstruck::derive_for(Outer => Debug)]
+        #[structstruck::each[derive(Debug)]]
+        struct Outer {
+            inner: struct {
+                deeper: struct {},
+            },
+            other: struct {},
+        }
+    };
+    let out = quote! {
+        #[derive(Debug)]
+        struct Deeper {}
+        #[derive(Clone,)]
+        #[derive(Debug)]
+        struct Inner {
+            deeper: Deeper,
+        }
+        #[derive(Debug)]
+        struct Other {}
+        #[derive(Debug)]
+        struct Outer {
+            inner: Inner,
+            other: Other,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn derive_for_invalid() {
+    let from = quote! {
+        #[structstruck::derive_for(Clone, Debug)]
+        struct Outer {}
+    };
+    let mut out = TokenStream::new();
+    recurse_through_definition(from, vec![], false, &mut out);
+    assert!(out.to_string().contains("expected e.g. derive_for"));
+}
+
+#[test]
+fn inner_attributes_at_depth() {
+    let from = quote! {
+        enum Outer {
+            Tuple(struct {
+                #![first]
+                middle: Option<struct {
+                    #![second]
+                    //! Innermost
+                    inner: union { #![third] x: u8 },
+                }>,
+            }),
+        }
+    };
+    let out = quote! {
+        #[third]
+        union Inner { x: u8 }
+        #[second]
+        /// Innermost
+        struct Middle { inner: Inner, }
+        #[first]
+        struct Tuple { middle: Option<Middle>, }
+        enum Outer {
+            Tuple(Tuple),
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn tuple_struct_in_union() {
+    let from = quote! {
+        union U {
+            a: struct T(u8),
+            b: core::mem::ManuallyDrop<struct (pub u16, u8)>,
+        }
+    };
+    let out = quote! {
+        struct T(u8);
+        struct B(pub u16, u8);
+        union U {
+            a: T,
+            b: core::mem::ManuallyDrop<B>,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn generate_only() {
+    let from = quote! {
+        #[structstruck::generate_only]
+        #[structstruck::each[derive(Debug)]]
+        #[derive(Clone)]
+        struct Outer {
+            inner: struct {
+                value: usize,
+            },
+        }
+    };
+    let out = quote! {
+        #[derive(Debug)]
+        struct Inner {
+            value: usize,
+        }
+        #[derive(Clone)]
+        struct Outer {
+            inner: struct {
+                value: usize,
+            },
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn lifetime_before_declaration() {
+    let from = quote! {
+        struct Outer<'a> {
+            text: Cow<'a, #[derive(Clone)] struct Owned { s: &'a str }>,
+            plain: Cow<'a, #[derive(Clone)] struct { n: u8 }>,
+        }
+    };
+    let out = quote! {
+        #[derive(Clone)]
+        struct Owned<'a> { s: &'a str }
+        #[derive(Clone)]
+        struct Plain { n: u8 }
+        struct Outer<'a> {
+            text: Cow<'a, Owned<'a> >,
+            plain: Cow<'a, Plain>,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn name_map() {
+    let from = quote! {
+        #[structstruck::name_map(suffix("_cfg") => suffix("Config"), name("data") => replace("Payload"))]
+        struct Server {
+            db_cfg: struct {
+                #![structstruck::name_map(prefix("is_") => prefix("Flag"))]
+                is_tls: enum { Yes, No },
+                cache_cfg: struct {},
+            },
+            data: struct {},
+            other: struct {},
+        }
+    };
+    let out = quote! {
+        enum FlagTls { Yes, No }
+        struct CacheConfig {}
+        struct DbConfig {
+            is_tls: FlagTls,
+            cache_cfg: CacheConfig,
+        }
+        struct Payload {}
+        struct Other {}
+        struct Server {
+            db_cfg: DbConfig,
+            data: Payload,
+            other: Other,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn name_map_invalid() {
+    let from = quote! {
+        #[structstruck::name_map(ends_with("_cfg") => suffix("Config"))]
+        struct Server {}
+    };
+    let mut out = TokenStream::new();
+    recurse_through_definition(from, vec![], false, &mut out);
+    assert!(out.to_string().contains("expected e.g. name_map"));
+}
+
+#[test]
+fn raw_identifiers_in_long_names() {
+    let from = quote! {
+        #[structstruck::each[structstruck::long_names]]
+        struct r#Move {
+            r#type: struct {},
+            r#ref: enum r#Match {
+                r#Loop { r#in: struct {} },
+            },
+        }
+    };
+    let out = quote! {
+        struct MoveType {}
+        struct MatchLoopIn {}
+        enum r#Match {
+            r#Loop { r#in: MatchLoopIn },
+        }
+        struct r#Move {
+            r#type: MoveType,
+            r#ref: r#Match,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn default_vis() {
+    let from = quote! {
+        #[structstruck::default_vis(pub(crate))]
+        pub struct Outer {
+            pub a: struct {
+                pub b: struct {},
+            },
+            c: struct {},
+            pub d: struct {
+                #![structstruck::vis(pub)]
+            },
+            e: struct {
+                #![structstruck::default_vis()]
+                pub f: struct {},
+            },
+        }
+    };
+    let out = quote! {
+        pub(crate) struct B {}
+        pub(crate) struct A {
+            pub b: B,
+        }
+        pub(crate) struct C {}
+        pub struct D {}
+        struct F {}
+        pub(crate) struct E {
+            pub f: F,
+        }
+        pub struct Outer {
+            pub a: A,
+            c: C,
+            pub d: D,
+            e: E,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn settings_from_each() {
+    let from = quote! {
+        #[structstruck::each[structstruck::emit(after)]]
+        struct Outer {
+            a: struct {
+                b: struct {},
+            },
+        }
+    };
+    let out = quote! {
+        struct Outer {
+            a: A,
+        }
+        struct A {
+            b: B,
+        }
+        struct B {}
+    };
+    check(from, out);
+
+    let from = quote! {
+        #[structstruck::each[structstruck::vis(pub(crate))]]
+        #[structstruck::each[structstruck::name_prefix = "My"]]
+        struct Outer {
+            a: struct {},
+        }
+    };
+    let out = quote! {
+        pub(crate) struct MyA {}
+        pub(crate) struct Outer {
+            a: MyA,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn empty_tuple_and_unit_children() {
+    let from = quote! {
+        struct Outer(struct Inner(), struct Unit, Option<struct Terminated;>);
+    };
+    let out = quote! {
+        struct Inner();
+        struct Unit;
+        struct Terminated;
+        struct Outer(Inner, Unit, Option<Terminated>);
+    };
+    check(from, out);
+}
+
+#[test]
+fn const_generic_defaults() {
+    let from = quote! {
+        struct Grid<T = struct { x: u8 }, const N: usize = { size_of::<u64>() * 2 }, const M: usize = { struct Meta { a: u8 } size_of::<Meta>() }> {
+            cells: [T; N],
+            meta: [u8; M],
+        }
+    };
+    let out = quote! {
+        struct GridT { x: u8 }
+        struct Grid<T = GridT, const N: usize = { size_of::<u64>() * 2 }, const M: usize = { struct Meta { a: u8 } size_of::<Meta>() }> {
+            cells: [T; N],
+            meta: [u8; M],
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn skip_pub_propagation() {
+    let from = quote! {
+        pub enum Outer {
+            Open(struct { x: u8 }),
+            #[structstruck::skip_pub_propagation]
+            Internal(struct { y: u8 }, struct { z: u8 }),
+            #[structstruck::skip_pub_propagation]
+            Named {
+                a: struct { a: u8 },
+            },
+        }
+    };
+    let out = quote! {
+        pub struct Open { x: u8 }
+        struct Internal { y: u8 }
+        struct Internal1 { z: u8 }
+        struct A { a: u8 }
+        pub enum Outer {
+            Open(Open),
+            Internal(Internal, Internal1),
+            Named {
+                a: A,
+            },
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn debug_skip() {
+    let from = quote! {
+        #[structstruck::each[derive(Clone, Debug)]]
+        struct Outer<T> {
+            inner: struct {
+                value: usize,
+                #[structstruck::debug_skip]
+                opaque: Opaque,
+            },
+            tuple: struct(#[structstruck::debug_skip] Opaque, T),
+            #[structstruck::debug_skip]
+            plain: Opaque,
+        }
+    };
+    let out = quote! {
+        #[derive(Clone,)]
+        struct Inner {
+            value: usize,
+            opaque: Opaque,
+        }
+        #[automatically_derived]
+        #[allow(clippy::all)]
+        impl ::core::fmt::Debug for Inner {
+            fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                f.debug_struct("Inner").field("value", &self.value).finish_non_exhaustive()
+            }
+        }
+        #[derive(Clone,)]
+        struct Tuple<T>(Opaque, T);
+        #[automatically_derived]
+        #[allow(clippy::all)]
+        impl<T> ::core::fmt::Debug for Tuple<T> where T: ::core::fmt::Debug, {
+            fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                f.debug_tuple("Tuple").field(&self.1).finish()
+            }
+        }
+        #[derive(Clone,)]
+        struct Outer<T> {
+            inner: Inner,
+            tuple: Tuple<T>,
+            plain: Opaque,
+        }
+        #[automatically_derived]
+        #[allow(clippy::all)]
+        impl<T> ::core::fmt::Debug for Outer<T> where T: ::core::fmt::Debug, {
+            fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                f.debug_struct("Outer").field("inner", &self.inner).field("tuple", &self.tuple).finish_non_exhaustive()
+            }
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn inherited_where_clauses() {
+    let from = quote! {
+        enum Outer<T, U>
+        where
+            T: Clone,
+            Vec<T>: From<U>,
+            U: Default,
+        {
+            A { a: struct { t: T } },
+            B { b: struct { u: U } },
+        }
+    };
+    let out = quote! {
+        struct A<T>
+        where
+            T: Clone,
+        {
+            t: T,
+        }
+        struct B<U>
+        where
+            U: Default,
+        {
+            u: U,
+        }
+        enum Outer<T, U>
+        where
+            T: Clone,
+            Vec<T>: From<U>,
+            U: Default,
+        {
+            A { a: A<T> },
+            B { b: B<U> },
+        }
+    };
+    check(from, out);
+
+    let from = quote! {
+        union Outer<T> where T: Copy {
+            a: struct { t: T },
+        }
+    };
+    let out = quote! {
+        struct A<T> where T: Copy { t: T }
+        union Outer<T> where T: Copy {
+            a: A<T>,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn errors_span_whole_construct() {
+    for from in [
+        quote! { struct Outer { a: u8 b: u16 } },
+        quote! { struct Outer { a: struct A {} struct B {} } },
+    ] {
+        let mut out = TokenStream::new();
+        recurse_through_definition(from, vec![], false, &mut out);
+        let out = out.to_string();
+        assert!(out.contains("compile_error"), "{}", out);
+        // Later errors may follow, e.g. from trying to parse the whole thing anyway
+        let first = out.split("compile_error").nth(1).unwrap();
+        assert!(!first.contains("starting from"), "{}", out);
+    }
+}
+
+#[test]
+fn each_cfg_attr() {
+    let from = quote! {
+        #[structstruck::each[cfg_attr(feature = "x", derive(Clone, Debug))]]
+        #[structstruck::each[cfg_attr(all(test, unix), allow(dead_code), doc = "Generated")]]
+        struct Outer {
+            inner: struct { alias: type = Vec<u8>, },
+        }
+    };
+    let out = quote! {
+        #[cfg_attr(all(test, unix), allow(dead_code), doc = "Generated")]
+        type Alias = Vec<u8>;
+        #[cfg_attr(feature = "x", derive(Clone, Debug))]
+        #[cfg_attr(all(test, unix), allow(dead_code), doc = "Generated")]
+        struct Inner { alias: Alias, }
+        #[cfg_attr(feature = "x", derive(Clone, Debug))]
+        #[cfg_attr(all(test, unix), allow(dead_code), doc = "Generated")]
+        struct Outer {
+            inner: Inner,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn allow_strikethrough() {
+    let from = quote! {
+        #[structstruck::allow_strikethrough]
+        #[strikethrough[derive(Debug)]]
+        struct Outer {
+            inner: struct {
+                #![strikethrough[derive(Clone)]]
+            },
+        }
+    };
+    let out = quote! {
+        #[derive(Clone)]
+        #[derive(Debug)]
+        struct Inner {}
+        #[derive(Debug)]
+        struct Outer {
+            inner: Inner,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn array_element() {
+    let from = quote! {
+        struct Outer {
+            cells: [struct Cell { v: u8 }; 4],
+            anon: Box<[struct { w: u8 }; if A > B { 1 } else { 2 }]>,
+            turbofish: [struct {}; size_of::<Vec<u8>>() * 2],
+        }
+    };
+    let out = quote! {
+        struct Cell { v: u8 }
+        struct Anon { w: u8 }
+        struct Turbofish {}
+        struct Outer {
+            cells: [Cell; 4],
+            anon: Box<[Anon; if A > B { 1 } else { 2 }]>,
+            turbofish: [Turbofish; size_of::<Vec<u8>>() * 2],
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn wrapper_names() {
+    let from = quote! {
+        #[structstruck::wrapper_names]
+        struct Outer {
+            engine: Box<struct { power: u32 }>,
+            wheels: Option<std::vec::Vec<struct { size: u8 }>>,
+            pair: Option<(struct { a: u8 }, u8)>,
+            named: Box<struct Named {}>,
+            plain: struct {},
+        }
+    };
+    let out = quote! {
+        struct BoxEngine { power: u32 }
+        struct VecWheels { size: u8 }
+        struct Pair { a: u8 }
+        struct Named {}
+        struct Plain {}
+        struct Outer {
+            engine: Box<BoxEngine>,
+            wheels: Option<std::vec::Vec<VecWheels> >,
+            pair: Option<(Pair, u8)>,
+            named: Box<Named>,
+            plain: Plain,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn nested_enum_discriminants() {
+    let from = quote! {
+        struct Response {
+            code: #[repr(u16)] enum Code {
+                Ok = 200,
+                NotFound = 404,
+                Other { detail: struct { msg: String } } = 500,
+                Teapot = 418,
+            },
+        }
+    };
+    let out = quote! {
+        struct Detail { msg: String }
+        #[repr(u16)]
+        enum Code {
+            Ok = 200,
+            NotFound = 404,
+            Other { detail: Detail } = 500,
+            Teapot = 418,
+        }
+        struct Response {
+            code: Code,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn tuple_type_alias() {
+    let from = quote! {
+        type Pair = (struct A { a: u8 }, Option<struct B { b: u8 }>, [struct C; 2]);
+    };
+    let out = quote! {
+        struct A { a: u8 }
+        struct B { b: u8 }
+        struct C;
+        type Pair = (A, Option<B>, [C; 2]);
+    };
+    check(from, out);
+}
+
+#[test]
+fn debug_type_tree_rendering() {
+    let mut out = TokenStream::new();
+    debug_type_tree(quote! { Box<Vec<u8>, A> }, &mut out);
+    let expected = "Box\n<\n  Vec\n  <\n    u8\n  >\n  ,\n  A\n>\n";
+    assert_eq!(
+        out.to_string(),
+        proc_macro2::Literal::string(expected).to_string()
+    );
+}
+
+#[test]
+fn impl_method_signatures() {
+    let from = quote! {
+        impl<T> Engine<T> {
+            #![allow(dead_code)]
+            const LIMIT: u32 = 3;
+            pub fn start(&mut self, config: struct { power: u32 }) -> Result<(), enum { Stalled, Broken }> {
+                let x: u8 = 0;
+                Ok(())
+            }
+            fn stop(&self) -> struct Stopped(u64) {
+                Stopped(0)
+            }
+            log!{}
+        }
+    };
+    let out = quote! {
+        pub struct Config { power: u32 }
+        enum Start { Stalled, Broken }
+        struct Stopped(u64);
+        impl<T> Engine<T> {
+            #![allow(dead_code)]
+            const LIMIT: u32 = 3;
+            pub fn start(&mut self, config: Config) -> Result<(), Start> {
+                let x: u8 = 0;
+                Ok(())
+            }
+            fn stop(&self) -> Stopped {
+                Stopped(0)
+            }
+            log!{}
+        }
+    };
+    check(from, out);
+
+    let from = quote! {
+        impl Engine {
+            fn gauges(&self) -> struct { rpm: u32 } {
+                todo!()
+            }
+            fn rows(&self) -> impl Iterator<Item = struct Row { id: u32 }> + '_ where Self: Sized {
+                todo!()
+            }
+        }
+    };
+    let out = quote! {
+        struct Gauges { rpm: u32 }
+        struct Row { id: u32 }
+        impl Engine {
+            fn gauges(&self) -> Gauges {
+                todo!()
+            }
+            fn rows(&self) -> impl Iterator<Item = Row> + '_ where Self: Sized {
+                todo!()
+            }
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn const_generic_defaults_unbraced() {
+    let from = quote! {
+        struct Grid<T: Clone = u8, const N: usize = 4> {
+            cells: [T; N],
+            row: struct { first: T },
+        }
+    };
+    let out = quote! {
+        struct Row<T: Clone = u8> { first: T }
+        struct Grid<T: Clone = u8, const N: usize = 4> {
+            cells: [T; N],
+            row: Row<T>,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn turbofish_in_field_type() {
+    let from = quote! {
+        struct Parent {
+            a: Foo::<struct Bar { b: u8 }>,
+            c: std::vec::Vec::<struct { d: u8 }>,
+        }
+    };
+    let out = quote! {
+        struct Bar { b: u8 }
+        struct C { d: u8 }
+        struct Parent {
+            a: Foo::<Bar>,
+            c: std::vec::Vec::<C>,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn each_if_copy() {
+    let from = quote! {
+        #[structstruck::each_if(copy)[derive(Clone, Copy)]]
+        struct Parent {
+            pos: struct { x: f32, y: f32 },
+            name: struct { s: String },
+            both: (struct Flag(bool), [u8; 4], &'static str),
+            kind: enum { A, B(struct { v: u8 }) },
+        }
+    };
+    let out = quote! {
+        #[derive(Clone, Copy,)]
+        struct Pos { x: f32, y: f32 }
+        struct Name { s: String }
+        #[derive(Clone, Copy,)]
+        struct Flag(bool);
+        #[derive(Clone, Copy,)]
+        struct B { v: u8 }
+        #[derive(Clone, Copy,)]
+        enum Kind { A, B(B) }
+        struct Parent {
+            pos: Pos,
+            name: Name,
+            both: (Flag, [u8; 4], &'static str),
+            kind: Kind,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn each_if_invalid() {
+    let from = quote! {
+        #[structstruck::each_if(clone)[derive(Clone)]]
+        struct Parent {}
+    };
+    let mut out = TokenStream::new();
+    recurse_through_definition(from, vec![], false, &mut out);
+    assert!(out.to_string().contains("expected each_if(copy)"));
+}
+
+#[test]
+fn alias_as() {
+    let from = quote! {
+        pub struct Outer<T: Clone> {
+            pub item: struct {
+                #![structstruck::alias_as(OuterItem)]
+                value: T,
+            },
+        }
+    };
+    let out = quote! {
+        pub struct Item<T: Clone> {
+            value: T,
+        }
+        pub type OuterItem<T> = Item<T>;
+        pub struct Outer<T: Clone> {
+            pub item: Item<T>,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn alias_as_associated_type() {
+    let from = quote! {
+        #[structstruck::alias_as(Iterator::Item)]
+        struct Outer {}
+    };
+    let mut out = TokenStream::new();
+    recurse_through_definition(from, vec![], false, &mut out);
+    assert!(out.to_string().contains("have to be set in the impl"));
+}
+
+#[test]
+fn attributes_before_nested_declarations() {
+    let from = quote! {
+        #[structstruck::each[derive(Debug)]]
+        enum Outer {
+            Wrapped(Option<#[derive(Clone)] struct { a: u8 }>),
+            Tuple(#[repr(C)] #[structstruck::each[derive(Copy)]] struct { b: (#[allow(dead_code)] struct Deep(u8), u8) }),
+        }
+    };
+    let out = quote! {
+        #[derive(Clone)]
+        #[derive(Debug)]
+        struct Wrapped { a: u8 }
+        #[allow(dead_code)]
+        #[derive(Debug)]
+        #[derive(Copy)]
+        struct Deep(u8);
+        #[repr(C)]
+        #[derive(Copy)]
+        #[derive(Debug)]
+        struct Tuple { b: (Deep, u8) }
+        #[derive(Debug)]
+        enum Outer {
+            Wrapped(Option<Wrapped>),
+            Tuple(Tuple),
+        }
+    };
+    check(from, out);
+
+    // Attributes for the field stay there
+    let from = quote! {
+        struct Outer(#[serde(skip)] #[derive(Clone)] struct Inner(u8));
+    };
+    let out = quote! {
+        #[derive(Clone)]
+        struct Inner(u8);
+        struct Outer(#[serde(skip)] Inner);
+    };
+    check(from, out);
+}
+
+#[test]
+fn sort_fields() {
+    let from = quote! {
+        #[structstruck::sort_fields]
+        struct Outer {
+            /// Last
+            zeta: u8,
+            #[serde(default)]
+            alpha: struct {
+                y: u8,
+                x: u8,
+            },
+            mid: enum {
+                Second(struct(u8, u16)),
+                First { b: u8, a: u8 },
+            },
+        }
+    };
+    let out = quote! {
+        struct Alpha {
+            x: u8,
+            y: u8,
+        }
+        struct Second(u8, u16);
+        enum Mid {
+            Second(Second),
+            First { b: u8, a: u8 },
+        }
+        struct Outer {
+            #[serde(default)]
+            alpha: Alpha,
+            mid: Mid,
+            /// Last
+            zeta: u8,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn self_in_nested_declarations() {
+    let from = quote! {
+        impl Chain {
+            fn link(&self, next: #[structstruck::accessors] struct Link(Option<Box<Self>>)) -> Self {
+                todo!()
+            }
+        }
+    };
+    let out = quote! {
+        struct Link(Option<Box<Self>>);
+        #[automatically_derived]
+        #[allow(dead_code)]
+        #[allow(clippy::all)]
+        impl Link {
+            fn value(&self) -> &Option<Box<Self>> {
+                &self.0
+            }
+        }
+        impl Chain {
+            fn link(&self, next: Link) -> Self {
+                todo!()
+            }
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn each_impl() {
+    let from = quote! {
+        #[structstruck::each_impl(Marker)]
+        #[structstruck::each_impl(crate::Named { const NAME: &'static str = "generated"; })]
+        struct Outer<T> {
+            inner: struct {
+                value: T,
+            },
+            alias: type = u8,
+        }
+    };
+    let out = quote! {
+        struct Inner<T> {
+            value: T,
+        }
+        impl<T> Marker for Inner<T> {}
+        impl<T> crate::Named for Inner<T> {
+            const NAME: &'static str = "generated";
+        }
+        type Alias = u8;
+        struct Outer<T> {
+            inner: Inner<T>,
+            alias: Alias,
+        }
+        impl<T> Marker for Outer<T> {}
+        impl<T> crate::Named for Outer<T> {
+            const NAME: &'static str = "generated";
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn each_impl_invalid() {
+    let from = quote! {
+        #[structstruck::each_impl]
+        struct Outer {}
+    };
+    let mut out = TokenStream::new();
+    recurse_through_definition(from, vec![], false, &mut out);
+    assert!(out.to_string().contains("expected a trait"));
+}
+
+#[test]
+fn own_lifetimes_not_inherited_twice() {
+    let from = quote! {
+        struct Outer<'a, T> {
+            slice: struct Window<'a> {
+                data: &'a [u8],
+                inner: struct { d: &'a u8 },
+            },
+            other: struct { t: &'a T },
+        }
+    };
+    let out = quote! {
+        struct Inner<'a> { d: &'a u8 }
+        struct Window<'a> {
+            data: &'a [u8],
+            inner: Inner<'a>,
+        }
+        struct Other<'a, T> { t: &'a T }
+        struct Outer<'a, T> {
+            slice: Window<'a>,
+            other: Other<'a, T>,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn each_invalid_attribute() {
+    for from in [
+        quote! {
+            #[structstruck::each[not valid attr syntax !!]]
+            struct Parent { a: struct {} }
+        },
+        quote! {
+            #[structstruck::each[]]
+            struct Parent {}
+        },
+    ] {
+        let mut out = TokenStream::new();
+        recurse_through_definition(from, vec![], false, &mut out);
+        let out = out.to_string();
+        assert!(out.contains("structstruck::each"), "{}", out);
+        assert!(out.contains("must be an attribute"), "{}", out);
+        assert!(!out.contains("# [not"), "{}", out);
+    }
+}
+
+#[test]
+fn wrapper_stacks() {
+    let from = quote! {
+        struct Task<T> {
+            fut: Pin<Box<struct Fut { out: T }>>,
+            anon: std::pin::Pin<Box<Option<struct { v: u8 }>>>,
+        }
+    };
+    let out = quote! {
+        struct Fut<T> { out: T }
+        struct Anon { v: u8 }
+        struct Task<T> {
+            fut: Pin<Box<Fut<T>>>,
+            anon: std::pin::Pin<Box<Option<Anon>>>,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn raw_names() {
+    let from = quote! {
+        #[structstruck::raw_names]
+        struct ffi_outer {
+            point_xy: struct { x: i32 },
+            r#type: enum {
+                tag_a(struct(u8)),
+                tag_b { nested_field: struct {} },
+            },
+            pair: (struct { a: u8 }, u8),
+        }
+    };
+    let out = quote! {
+        struct point_xy { x: i32 }
+        struct tag_a(u8);
+        struct nested_field {}
+        enum r#type {
+            tag_a(tag_a),
+            tag_b { nested_field: nested_field },
+        }
+        struct pair { a: u8 }
+        struct ffi_outer {
+            point_xy: point_xy,
+            r#type: r#type,
+            pair: (pair, u8),
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn raw_names_positional() {
+    let from = quote! {
+        #[structstruck::raw_names]
+        struct ffi_outer {
+            r#type: Result<struct { ok: u8 }, struct { err: u8 }>,
+            r#fn: Box<dyn Fn(u8) -> struct { out: u8 }>,
+        }
+    };
+    let out = quote! {
+        struct r#type { ok: u8 }
+        struct type1 { err: u8 }
+        struct fnOutput { out: u8 }
+        struct ffi_outer {
+            r#type: Result<r#type, type1>,
+            r#fn: Box<dyn Fn(u8) -> fnOutput>,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn fn_generic_bounds() {
+    let from = quote! {
+        fn convert<'a, T: Into<struct Arg { v: u8 }> + Clone, U: 'a + AsRef<struct { s: u8 }> = ()>(t: T, u: &'a U) {}
+    };
+    let out = quote! {
+        struct Arg { v: u8 }
+        struct ConvertU { s: u8 }
+        fn convert<'a, T: Into<Arg> + Clone, U: 'a + AsRef<ConvertU> = ()>(t: T, u: &'a U) {}
+    };
+    check(from, out);
+}
+
+#[test]
+fn transparent() {
+    let from = quote! {
+        struct Outer {
+            inner: #[structstruck::transparent] struct Inner { value: u32 },
+            list: Vec<struct {
+                #![structstruck::transparent]
+                item: struct { x: u8 },
+            }>,
+            meters: #[structstruck::transparent] struct(f64),
+        }
+    };
+    let out = quote! {
+        struct Item { x: u8 }
+        struct Outer {
+            inner: u32,
+            list: Vec<Item>,
+            meters: f64,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn transparent_invalid() {
+    let from = quote! {
+        struct Outer {
+            inner: #[structstruck::transparent] struct { a: u8, b: u8 },
+        }
+    };
+    let mut out = TokenStream::new();
+    recurse_through_definition(from, vec![], false, &mut out);
+    let out = out.to_string();
+    assert!(out.contains("exactly one field"), "{}", out);
+    assert!(!out.contains("transparent ]"), "{}", out);
+}
+
+#[test]
+fn name_collides_with_parent() {
+    for from in [
+        quote! {
+            enum Foo { Bar { foo: struct { x: u8 } } }
+        },
+        quote! {
+            struct Foo { a: struct { b: Option<struct Foo {}> } }
+        },
+    ] {
+        let mut out = TokenStream::new();
+        recurse_through_definition(from, vec![], false, &mut out);
+        let out = out.to_string();
+        assert!(
+            out.contains("Nested declaration named Foo like the declaration it is in"),
+            "{}",
+            out
+        );
+    }
+}
+
+#[test]
+fn generated_declarations_list() {
+    let mut out = TokenStream::new();
+    generated_declarations(
+        quote! {
+            #[structstruck::each_impl(Marker)]
+            #[structstruck::reexport(types)]
+            struct Outer {
+                inner: struct { value: usize },
+            }
+        },
+        &mut out,
+    );
+    let out = out.to_string();
+    assert!(!out.contains("compile_error"), "{}", out);
+    assert!(
+        out.contains(r#"("Inner" , "struct Inner { value : usize }")"#),
+        "{}",
+        out
+    );
+    assert!(
+        out.contains(r#"("Outer" , "struct Outer { inner : Inner , }")"#),
+        "{}",
+        out
+    );
+    assert!(!out.contains("impl"), "{}", out);
+    assert!(!out.contains("types"), "{}", out);
+
+    let mut out = TokenStream::new();
+    generated_declarations(
+        quote! {
+            #[structstruck::numbering(sometimes)]
+            struct Outer(struct {});
+        },
+        &mut out,
+    );
+    let out = out.to_string();
+    assert!(out.starts_with("{ compile_error !"), "{}", out);
+}
+
+#[test]
+fn inherit_field_attrs() {
+    let from = quote! {
+        #[structstruck::inherit_field_attrs(serde, doc)]
+        struct Outer {
+            /// The inner part
+            #[serde(default)]
+            #[allow(unused)]
+            inner: struct {
+                #[serde(rename = "d")]
+                deeper: struct {},
+            },
+        }
+    };
+    let out = quote! {
+        #[serde(rename = "d")]
+        struct Deeper {}
+        /// The inner part
+        #[serde(default)]
+        struct Inner {
+            #[serde(rename = "d")]
+            deeper: Deeper,
+        }
+        struct Outer {
+            /// The inner part
+            #[serde(default)]
+            #[allow(unused)]
+            inner: Inner,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn const_fn() {
+    let from = quote! {
+        pub const fn origin(scale: struct Scale(u8)) -> struct Origin {
+            Origin
+        }
+    };
+    let out = quote! {
+        pub struct Scale(u8);
+        pub struct Origin;
+        pub const fn origin(scale: Scale) -> Origin {
+            Origin
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn name_config() {
+    let from = quote! {
+        #[structstruck::config(separator = "_")]
+        #[structstruck::each[structstruck::long_names]]
+        struct Outer {
+            inner: struct {
+                deep_field: struct {},
+            },
+        }
+    };
+    let out = quote! {
+        struct Outer_Inner_DeepField {}
+        struct Outer_Inner {
+            deep_field: Outer_Inner_DeepField,
+        }
+        struct Outer {
+            inner: Outer_Inner,
+        }
+    };
+    check(from, out);
+
+    let from = quote! {
+        #[structstruck::config(case = "raw", suffix = "_t")]
+        struct outer {
+            inner: struct {},
+        }
+    };
+    let out = quote! {
+        struct inner_t {}
+        struct outer {
+            inner: inner_t,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn name_config_invalid() {
+    let from = quote! {
+        #[structstruck::config(separator = "_", casing = "raw")]
+        struct Outer {}
+    };
+    let mut out = TokenStream::new();
+    recurse_through_definition(from, vec![], false, &mut out);
+    let out = out.to_string();
+    assert!(out.contains("unknown key casing"), "{}", out);
+}
+
+#[test]
+fn serde_internally_tagged_tuple_variant() {
+    let from = quote! {
+        #[structstruck::each[serde(tag = "type")]]
+        struct Message {
+            body: enum {
+                Text { content: String },
+                Pair(u32, u32),
+            }
+        }
+    };
+    let mut to = TokenStream::new();
+    recurse_through_definition(from, vec![], false, &mut to);
+    assert!(to.to_string().contains("compile_error"));
+    assert!(to.to_string().contains("Variant Pair"));
+}
+
+#[test]
+fn serde_internally_tagged_struct_variants() {
+    let from = quote! {
+        #[serde(tag = "type", content = "data")]
+        enum Adjacent {
+            Pair(u32, u32),
+        }
+    };
+    let mut to = TokenStream::new();
+    recurse_through_definition(from, vec![], false, &mut to);
+    assert!(!to.to_string().contains("compile_error"));
+    let from = quote! {
+        #[structstruck::each[serde(tag = "type")]]
+        enum Event {
+            Click(struct { x: u32, y: u32 }),
+            Key { code: u32 },
+            Quit,
+        }
+    };
+    let mut to = TokenStream::new();
+    recurse_through_definition(from, vec![], false, &mut to);
+    assert!(!to.to_string().contains("compile_error"));
+}
+
+#[test]
+fn dyn_associated_type_with_bounds() {
+    let from = quote! {
+        struct Table {
+            rows: Box<dyn Iterator<Item = struct Row { id: u32 }> + Send + Sync + 'static>,
+            cells: Box<dyn Iterator<Item = struct { v: u8 }> + Send>,
+        }
+    };
+    let out = quote! {
+        struct Row { id: u32 }
+        struct Item { v: u8 }
+        struct Table {
+            rows: Box<dyn Iterator<Item = Row> + Send + Sync + 'static>,
+            cells: Box<dyn Iterator<Item = Item> + Send>,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn vis_in_generic_arguments() {
+    let from = quote! {
+        struct Outer {
+            shared: Arc<pub(crate) struct Inner { v: u8 }>,
+            list: Vec<pub struct { id: u32 }>,
+            map: HashMap<String, pub(in crate::a) struct Entry { e: u8 }>,
+        }
+    };
+    let out = quote! {
+        pub(crate) struct Inner { v: u8 }
+        pub struct List { id: u32 }
+        pub(in crate::a) struct Entry { e: u8 }
+        struct Outer {
+            shared: Arc<Inner>,
+            list: Vec<List>,
+            map: HashMap<String, Entry>,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn vis_in_generic_arguments_of_pub_field() {
+    let from = quote! {
+        pub struct Outer {
+            pub shared: Arc<pub(crate) struct Inner { v: u8 }>,
+        }
+    };
+    let out = quote! {
+        pub(crate) struct Inner { v: u8 }
+        pub struct Outer {
+            pub shared: Arc<Inner>,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn allow_dead_code() {
+    let from = quote! {
+        #[structstruck::allow_dead_code]
+        #[derive(Debug)]
+        struct Outer {
+            inner: struct {
+                deep: enum { A, B },
+            },
+            #[cfg(feature = "extra")]
+            extra: struct Extra {},
+        }
+    };
+    let out = quote! {
+        #[allow(dead_code)]
+        enum Deep { A, B }
+        #[allow(dead_code)]
+        struct Inner {
+            deep: Deep,
+        }
+        #[allow(dead_code)]
+        #[cfg(feature = "extra")]
+        struct Extra {}
+        #[derive(Debug)]
+        struct Outer {
+            inner: Inner,
+            #[cfg(feature = "extra")]
+            extra: Extra,
+        }
+    };
+    check(from, out);
+}
+
+#[test]
+fn map_names() {
+    let from = quote! {
+        #[structstruck::each[structstruck::map_names]]
+        struct Index {
+            entries: HashMap<struct { id: u32 }, struct { name: String }>,
+            sorted: std::collections::BTreeMap<u32, struct { rank: u8 }>,
+            pair: Result<struct { ok: u8 }, struct { err: u8 }>,
+        }
+    };
+    let out = quote! {
+        struct EntriesKey { id: u32 }
+        struct EntriesValue { name: String }
+        struct SortedValue { rank: u8 }
+        struct Pair { ok: u8 }
+        struct Pair1 { err: u8 }
+        struct Index {
+            entries: HashMap<EntriesKey, EntriesValue>,
+            sorted: std::collections::BTreeMap<u32, SortedValue>,
+            pair: Result<Pair, Pair1>,
+        }
+    };
+    check(from, out);
+
+    let from = quote! {
+        struct Index {
+            entries: HashMap<struct { id: u32 }, struct { name: String }>,
+        }
+    };
+    let out = quote! {
+        struct Entries { id: u32 }
+        struct Entries1 { name: String }
+        struct Index {
+            entries: HashMap<Entries, Entries1>,
+        }
+    };
+    check(from, out);
+
+    let from = quote! {
+        #[structstruck::each[structstruck::map_names]]
+        #[structstruck::raw_names]
+        struct index {
+            r#type: HashMap<struct { id: u32 }, struct { name: String }>,
+        }
+    };
+    let out = quote! {
+        struct typeKey { id: u32 }
+        struct typeValue { name: String }
+        struct index {
+            r#type: HashMap<typeKey, typeValue>,
+        }
+    };
+    check(from, out);
+}